@@ -1,55 +1,56 @@
 use std::{
     fmt::{self},
     string::FromUtf8Error,
+    time::Duration,
 };
 
-use crate::backup::{
-    logger::LogLevel,
-    notification::{send_notification, Discord, Gotify},
+use crate::backup::notification::{
+    send_notifications, send_summary_notifications, DestinationOutcome, NotificationContent,
+    NotificationMode,
 };
+use crate::backup::utils::{format_duration, format_size};
 
 use super::DockerBackup;
 
 #[derive(Debug)]
 pub struct BackupError {
     pub message: String,
+    pub destination: Option<String>,
+    /// Set when the run was stopped with Ctrl+C instead of failing.
+    pub interrupted: bool,
 }
 
 impl BackupError {
     pub fn new(message: &str) -> BackupError {
         BackupError {
             message: message.to_string(),
+            destination: None,
+            interrupted: false,
         }
     }
-    pub fn notify(&self, config: &DockerBackup) {
-        if let Some(gotify_url) = &config.gotify_url {
-            send_notification::<Gotify>(Gotify {
-                message: Some(format!("Backup failed with error: {}", self.message)),
-                success: false,
-                url: gotify_url,
-                logger: &config.logger,
-            })
-            .unwrap_or_else(|e| {
-                config.logger.log(
-                    &format!("Error sending gotify notification: {}", e),
-                    LogLevel::Error,
-                );
-            });
+    pub fn interrupted() -> BackupError {
+        BackupError {
+            interrupted: true,
+            ..BackupError::new("Backup interrupted")
         }
-
-        if let Some(dc_url) = &config.discord_url {
-            send_notification::<Discord>(Discord {
-                message: Some(self.message.to_string()),
+    }
+    pub fn with_destination(mut self, destination: String) -> BackupError {
+        self.destination = Some(destination);
+        self
+    }
+    pub fn notify(&self, config: &DockerBackup) {
+        send_notifications(
+            config,
+            &NotificationContent {
                 success: false,
-                url: dc_url,
-            })
-            .unwrap_or_else(|e| {
-                config.logger.log(
-                    &format!("Error sending discord notification: {}", e),
-                    LogLevel::Error,
-                );
-            });
-        }
+                message: self.message.clone(),
+                destination: self.destination.clone(),
+                duration: None,
+                size: None,
+                errors: vec![self.message.clone()],
+                ..Default::default()
+            },
+        );
     }
 }
 
@@ -63,66 +64,228 @@ impl std::error::Error for BackupError {}
 
 impl From<std::io::Error> for BackupError {
     fn from(error: std::io::Error) -> Self {
-        BackupError {
-            message: error.to_string(),
-        }
+        BackupError::new(&error.to_string())
     }
 }
 
 impl From<FromUtf8Error> for BackupError {
     fn from(error: FromUtf8Error) -> Self {
-        BackupError {
-            message: error.to_string(),
-        }
+        BackupError::new(&error.to_string())
     }
 }
 
 impl Default for BackupError {
     fn default() -> Self {
-        BackupError {
-            message: "An error occurred while parsing the HTTP request".to_string(),
-        }
+        BackupError::new("An error occurred while parsing the HTTP request")
     }
 }
 
 pub struct BackupSuccess {
-    message: String,
+    pub message: String,
+    pub destination: String,
+    pub duration: Duration,
+    pub size: u64,
+    /// Bytes sent to the destination, for destinations that report them.
+    pub transferred: Option<u64>,
 }
 
 impl BackupSuccess {
-    pub fn new(message: &str) -> Self {
+    pub fn new(message: &str, destination: String, duration: Duration, size: u64) -> Self {
         BackupSuccess {
             message: message.to_string(),
+            destination,
+            duration,
+            size,
+            transferred: None,
         }
     }
+    pub fn with_transferred(mut self, transferred: Option<u64>) -> BackupSuccess {
+        self.transferred = transferred;
+        self
+    }
     pub fn notify(&self, config: &DockerBackup) {
-        if let Some(gotify_url) = &config.gotify_url {
-            send_notification::<Gotify>(Gotify {
-                message: Some(self.message.clone()),
+        send_notifications(
+            config,
+            &NotificationContent {
                 success: true,
-                url: gotify_url,
-                logger: &config.logger,
-            })
-            .unwrap_or_else(|e| {
-                config.logger.log(
-                    &format!("Error sending gotify notification: {}", e),
-                    LogLevel::Error,
-                );
-            });
+                message: self.message.clone(),
+                destination: Some(self.destination.clone()),
+                duration: Some(self.duration),
+                size: Some(self.size),
+                recovery: config.previous_results.get(&self.destination) == Some(&false),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// Outcome of a run, reported as the process exit code.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RunStatus {
+    Success,
+    /// Some destinations failed or the run finished with warnings, e.g. containers that
+    /// could not be started again.
+    PartialFailure,
+    Failure,
+    Interrupted,
+    /// Docker or the containers could not be handled, no destination was started.
+    PreflightFailure,
+}
+
+impl RunStatus {
+    pub fn code(&self) -> i32 {
+        match self {
+            RunStatus::Success => 0,
+            RunStatus::PartialFailure => 3,
+            RunStatus::Failure => 4,
+            RunStatus::PreflightFailure => 5,
+            RunStatus::Interrupted => 130,
         }
+    }
 
-        if let Some(dc_url) = &config.discord_url {
-            send_notification::<Discord>(Discord {
-                message: Some(self.message.clone()),
-                success: true,
-                url: dc_url,
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunStatus::Success => "success",
+            RunStatus::PartialFailure => "partial_failure",
+            RunStatus::Failure => "failure",
+            RunStatus::PreflightFailure => "preflight_failure",
+            RunStatus::Interrupted => "interrupted",
+        }
+    }
+}
+
+pub struct RunSummary<'a> {
+    pub results: &'a [Result<BackupSuccess, BackupError>],
+    pub duration: Duration,
+    pub stopped_containers: Vec<String>,
+    pub restarted_containers: Vec<String>,
+    pub warnings: Vec<String>,
+    /// Unusual volume sizes, notified as warnings without failing the run.
+    pub anomalies: Vec<String>,
+}
+
+impl<'a> RunSummary<'a> {
+    pub fn status(&self) -> RunStatus {
+        let failed = self.results.iter().filter(|result| result.is_err()).count();
+        if self
+            .results
+            .iter()
+            .any(|result| result.as_ref().is_err_and(|err| err.interrupted))
+        {
+            RunStatus::Interrupted
+        } else if failed == 0 && self.warnings.is_empty() {
+            RunStatus::Success
+        } else if failed == self.results.len() {
+            RunStatus::Failure
+        } else {
+            RunStatus::PartialFailure
+        }
+    }
+
+    pub fn content(&self) -> NotificationContent {
+        let outcomes: Vec<DestinationOutcome> = self
+            .results
+            .iter()
+            .map(|result| match result {
+                Ok(success) => DestinationOutcome {
+                    destination: success.destination.clone(),
+                    success: true,
+                    detail: format_duration(success.duration),
+                },
+                Err(err) => DestinationOutcome {
+                    destination: err
+                        .destination
+                        .clone()
+                        .unwrap_or_else(|| String::from("backup")),
+                    success: false,
+                    detail: err.message.clone(),
+                },
             })
-            .unwrap_or_else(|e| {
-                config.logger.log(
-                    &format!("Error sending discord notification: {}", e),
-                    LogLevel::Error,
-                );
-            });
+            .collect();
+        let succeeded = outcomes.iter().filter(|outcome| outcome.success).count();
+        let size = self
+            .results
+            .iter()
+            .filter_map(|result| result.as_ref().ok().map(|success| success.size))
+            .max();
+
+        let mut message = format!(
+            "Backup finished: {} of {} destinations succeeded\n",
+            succeeded,
+            outcomes.len()
+        );
+        for outcome in &outcomes {
+            message.push_str(&format!(
+                "[{}] {}: {}\n",
+                if outcome.success { "OK" } else { "FAILED" },
+                outcome.destination,
+                outcome.detail
+            ));
+        }
+        if let Some(size) = size {
+            message.push_str(&format!("Total size: {}\n", format_size(size)));
+        }
+        if !self.stopped_containers.is_empty() {
+            message.push_str(&format!(
+                "Stopped containers: {}\n",
+                self.stopped_containers.join(", ")
+            ));
+        }
+        if !self.restarted_containers.is_empty() {
+            message.push_str(&format!(
+                "Restarted containers: {}\n",
+                self.restarted_containers.join(", ")
+            ));
+        }
+        for warning in &self.warnings {
+            message.push_str(&format!("Warning: {}\n", warning));
+        }
+        for anomaly in &self.anomalies {
+            message.push_str(&format!("Size anomaly: {}\n", anomaly));
+        }
+
+        NotificationContent {
+            success: succeeded == outcomes.len() && self.warnings.is_empty(),
+            message: message.trim_end().to_string(),
+            destination: None,
+            duration: Some(self.duration),
+            size,
+            errors: outcomes
+                .iter()
+                .filter(|outcome| !outcome.success)
+                .map(|outcome| format!("{}: {}", outcome.destination, outcome.detail))
+                .collect(),
+            outcomes,
+            stopped_containers: self.stopped_containers.clone(),
+            restarted_containers: self.restarted_containers.clone(),
+            warnings: self.warnings.clone(),
+            anomalies: self.anomalies.clone(),
+            recovery: false,
+        }
+    }
+
+    pub fn notify(&self, config: &DockerBackup) {
+        let mut content = self.content();
+        content.recovery = content.success && config.previous_results.get("run") == Some(&false);
+        if config.notification_mode == NotificationMode::Summary {
+            send_notifications(config, &content);
+        } else if !content.anomalies.is_empty() {
+            //per-destination notifications don't carry the anomalies of the run
+            send_notifications(
+                config,
+                &NotificationContent {
+                    success: true,
+                    message: content
+                        .anomalies
+                        .iter()
+                        .map(|anomaly| format!("Size anomaly: {}", anomaly))
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                    anomalies: content.anomalies.clone(),
+                    ..Default::default()
+                },
+            );
         }
+        send_summary_notifications(config, &content);
     }
 }
@@ -1,11 +1,21 @@
 use std::{
     collections::HashSet,
     fs,
-    path::Path,
-    process::{Child, Command, Stdio},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
 };
 
-use crate::backup::{backup_result::BackupError, TargetOs};
+use crate::backup::{
+    backup_result::BackupError,
+    utils::{format_size, get_hostname},
+    TargetOs,
+};
 
 #[derive(Debug, Clone)]
 pub struct LocalDestination {
@@ -17,6 +27,44 @@ pub struct SshDestination {
     pub host: String,
     pub path: String,
     pub target_os: TargetOs,
+    pub transferred: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResticDestination {
+    pub repository: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct BorgDestination {
+    pub repository: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RcloneDestination {
+    pub remote: String,
+}
+
+/// Destination handled by an external `dockerbackup-dest-<scheme>` executable.
+#[derive(Debug, Clone)]
+pub struct PluginDestination {
+    pub url: String,
+    pub executable: PathBuf,
+}
+
+pub enum OutputLine {
+    Progress(String),
+    /// Total number of bytes transferred so far.
+    Transferred(u64),
+    Message(String),
+    Ignore,
+}
+
+/// Latest progress reported by a running backup.
+#[derive(Default)]
+pub struct Progress {
+    pub message: Option<String>,
+    pub transferred: Option<u64>,
 }
 
 pub trait BackupDestination: std::fmt::Debug + Send + Sync {
@@ -43,6 +91,39 @@ pub trait BackupDestination: std::fmt::Debug + Send + Sync {
         new_dir: &str,
     ) -> Result<Child, BackupError>;
     fn get_display_name(&self) -> String;
+
+    fn parse_output_line(&self, line: &str) -> OutputLine {
+        OutputLine::Message(line.to_string())
+    }
+    /// Bytes transferred by destinations that count them outside of the process output.
+    fn transferred_bytes(&self) -> Option<u64> {
+        None
+    }
+    fn prune(&self, _keep_last: u64) -> Result<(), BackupError> {
+        Ok(())
+    }
+    /// Stores the log of this run as `<new_dir>.log` next to the backup.
+    fn store_run_log(&self, _new_dir: &str, _log: &str) -> Result<(), BackupError> {
+        Err(BackupError::new(
+            "Storing the run log is not supported by this destination",
+        ))
+    }
+    /// Extracts the volumes of the backup in `backup_dir` into `volume_path`, every volume
+    /// when `volumes` is empty. Returns the restored volumes.
+    fn restore(
+        &self,
+        _backup_dir: &str,
+        _volumes: &[String],
+        _volume_path: &Path,
+    ) -> Result<Vec<String>, BackupError> {
+        Err(BackupError::new(
+            "Restoring is not supported by this destination",
+        ))
+    }
+    /// Stops a running backup started by `spawn_backup`.
+    fn kill(&self, child: &mut Child) -> std::io::Result<()> {
+        child.kill()
+    }
 }
 
 impl BackupDestination for LocalDestination {
@@ -95,8 +176,10 @@ impl BackupDestination for LocalDestination {
 
         let exec_rsync = rsync
             .arg("-aW")
+            .arg("--info=progress2")
             .arg(volume_path)
             .arg(Path::new(&self.path).join(new_dir))
+            .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
             .map_err(|e| BackupError::new(&format!("Failed to spawn rsync: {}", e)))?;
@@ -107,6 +190,25 @@ impl BackupDestination for LocalDestination {
     fn get_display_name(&self) -> String {
         self.path.clone()
     }
+
+    fn store_run_log(&self, new_dir: &str, log: &str) -> Result<(), BackupError> {
+        fs::write(Path::new(&self.path).join(format!("{}.log", new_dir)), log)?;
+        Ok(())
+    }
+
+    fn parse_output_line(&self, line: &str) -> OutputLine {
+        //--info=progress2 lines look like "  1,234,567  12%  10.00MB/s  0:00:10 (xfr#5, to-chk=10/20)"
+        let mut columns = line.split_whitespace();
+        if let (Some(bytes), Some(percent)) = (columns.next(), columns.next()) {
+            if percent.ends_with('%') {
+                let bytes: String = bytes.chars().filter(|c| c.is_ascii_digit()).collect();
+                if let Ok(bytes) = bytes.parse::<u64>() {
+                    return OutputLine::Transferred(bytes);
+                }
+            }
+        }
+        OutputLine::Message(line.to_string())
+    }
 }
 
 impl BackupDestination for SshDestination {
@@ -178,13 +280,17 @@ impl BackupDestination for SshDestination {
         excluded_volumes: &[String],
         new_dir: &str,
     ) -> Result<Child, BackupError> {
+        if self.target_os == TargetOs::Windows {
+            return self.spawn_archive_backup(volume_path, excluded_volumes, new_dir);
+        }
+
         let mut tar_volumes = Command::new("tar");
 
         tar_volumes.arg("-cf-").arg("-C").arg(volume_path);
 
         exclude_volumes(&mut tar_volumes, excluded_volumes, volume_path)?;
 
-        let tar_exec = tar_volumes
+        let mut tar_exec = tar_volumes
             .arg(".")
             .stdout(Stdio::piped())
             .spawn()
@@ -192,7 +298,7 @@ impl BackupDestination for SshDestination {
 
         let dest_path = append_to_path(&self.path, new_dir, &self.target_os);
 
-        let ssh = Command::new("ssh")
+        let mut ssh = Command::new("ssh")
             .arg(&self.host)
             .arg("mkdir")
             .arg(&dest_path)
@@ -201,17 +307,920 @@ impl BackupDestination for SshDestination {
             .arg("-C")
             .arg(dest_path)
             .arg("-xf-")
-            .stdin(Stdio::from(tar_exec.stdout.unwrap()))
+            .stdin(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
             .map_err(|e| BackupError::new(&format!("Failed to spawn ssh: {}", e)))?;
 
+        //copy the archive to ssh ourselves so the transferred bytes can be counted
+        let mut archive = tar_exec.stdout.take().unwrap();
+        let mut remote = ssh.stdin.take().unwrap();
+        let transferred = Arc::clone(&self.transferred);
+        transferred.store(0, Ordering::Relaxed);
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64 * 1024];
+            loop {
+                match archive.read(&mut buffer) {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => {
+                        if remote.write_all(&buffer[..read]).is_err() {
+                            break;
+                        }
+                        transferred.fetch_add(read as u64, Ordering::Relaxed);
+                    }
+                }
+            }
+            //dropping both ends signals eof to ssh and stops tar if ssh went away
+            drop(remote);
+            drop(archive);
+            let _ = tar_exec.wait();
+        });
+
         Ok(ssh)
     }
 
     fn get_display_name(&self) -> String {
         format!("{}:{}", self.host, self.path)
     }
+
+    fn store_run_log(&self, new_dir: &str, log: &str) -> Result<(), BackupError> {
+        if self.target_os == TargetOs::Windows {
+            return Err(BackupError::new(
+                "Storing the run log is not supported on windows targets",
+            ));
+        }
+        let mut ssh = Command::new("ssh");
+        ssh.arg(&self.host).arg(format!(
+            "cat > '{}.log'",
+            append_to_path(&self.path, new_dir, &self.target_os)
+        ));
+        run_with_input(&mut ssh, log)
+    }
+
+    fn transferred_bytes(&self) -> Option<u64> {
+        match self.target_os {
+            TargetOs::Unix => Some(self.transferred.load(Ordering::Relaxed)),
+            TargetOs::Windows => None,
+        }
+    }
+
+    fn restore(
+        &self,
+        backup_dir: &str,
+        volumes: &[String],
+        volume_path: &Path,
+    ) -> Result<Vec<String>, BackupError> {
+        check_name("backup", backup_dir)?;
+        let backup_path = append_to_path(&self.path, backup_dir, &self.target_os);
+        let volumes = if volumes.is_empty() {
+            self.list_backup_volumes(&backup_path)?
+        } else {
+            volumes.to_vec()
+        };
+        //names end up in remote commands, whether they were given or listed on the target
+        for volume in &volumes {
+            check_name("volume", volume)?;
+        }
+        if volumes.is_empty() {
+            return Err(BackupError::new(&format!(
+                "No volumes found in {}:{}",
+                self.host, backup_path
+            )));
+        }
+
+        for volume in &volumes {
+            //both layouts are read back as a tar stream of the volume directory
+            let read_command = match self.target_os {
+                TargetOs::Unix => format!(
+                    "tar -cf- -C {} {}",
+                    shell_quote(&backup_path),
+                    shell_quote(volume)
+                ),
+                TargetOs::Windows => format!(
+                    "powershell -NoProfile -NonInteractive -Command \"$in = [IO.File]::OpenRead('{}'); $in.CopyTo([Console]::OpenStandardOutput()); $in.Close()\"",
+                    append_to_path(&backup_path, &format!("{}.tar", volume), &self.target_os)
+                        .replace('\'', "''")
+                ),
+            };
+            self.extract_remote_archive(&read_command, volume_path)
+                .map_err(|err| {
+                    BackupError::new(&format!("Failed to restore volume {}: {}", volume, err))
+                })?;
+        }
+        Ok(volumes)
+    }
+
+    fn kill(&self, child: &mut Child) -> std::io::Result<()> {
+        match self.target_os {
+            TargetOs::Unix => child.kill(),
+            TargetOs::Windows => kill_process_group(child),
+        }
+    }
+}
+
+impl SshDestination {
+    /// Windows can't represent Linux ownership, modes, symlinks or xattrs, so each volume
+    /// is streamed into its own `<volume>.tar` instead of being extracted on the target.
+    fn spawn_archive_backup(
+        &self,
+        volume_path: &Path,
+        excluded_volumes: &[String],
+        new_dir: &str,
+    ) -> Result<Child, BackupError> {
+        check_excluded_volumes(excluded_volumes, volume_path)?;
+
+        let dest_path = append_to_path(&self.path, new_dir, &self.target_os);
+
+        let mut script = Command::new("bash");
+        script
+            .arg("-c")
+            .arg(ARCHIVE_SCRIPT)
+            .arg("bash")
+            .arg(&self.host)
+            .arg(format!("mkdir \"{}\"", dest_path))
+            .arg(volume_path);
+
+        for volume in list_volumes(volume_path, excluded_volumes)? {
+            let archive_path =
+                append_to_path(&dest_path, &format!("{}.tar", volume), &self.target_os);
+            script.arg(&volume).arg(format!(
+                "powershell -NoProfile -NonInteractive -Command \"$out = [IO.File]::Create('{}'); [Console]::OpenStandardInput().CopyTo($out); $out.Close()\"",
+                archive_path.replace('\'', "''")
+            ));
+        }
+
+        //tar and ssh run as children of bash, its own process group lets kill stop them all
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut script, 0);
+
+        let exec_script = script
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| BackupError::new(&format!("Failed to spawn bash: {}", e)))?;
+
+        Ok(exec_script)
+    }
+
+    /// Names of the volumes stored in a backup directory on the target.
+    fn list_backup_volumes(&self, backup_path: &str) -> Result<Vec<String>, BackupError> {
+        let list_command = match self.target_os {
+            TargetOs::Unix => format!("ls -1 {}", shell_quote(backup_path)),
+            TargetOs::Windows => format!(
+                "powershell -NoProfile -NonInteractive -Command \"Get-ChildItem -LiteralPath '{}' -Filter *.tar -Name\"",
+                backup_path.replace('\'', "''")
+            ),
+        };
+        let output = Command::new("ssh")
+            .arg(&self.host)
+            .arg(list_command)
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute ssh: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(BackupError::new(&format!(
+                "Failed to list backup {}:{}: {}",
+                self.host,
+                backup_path,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.strip_suffix(".tar").unwrap_or(name).to_string())
+            .collect())
+    }
+
+    /// Pipes the tar stream printed by `read_command` on the target into a local tar,
+    /// restoring numeric ownership, modes, xattrs and ACLs.
+    fn extract_remote_archive(
+        &self,
+        read_command: &str,
+        volume_path: &Path,
+    ) -> Result<(), BackupError> {
+        let mut ssh = Command::new("ssh")
+            .arg(&self.host)
+            .arg(read_command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| BackupError::new(&format!("Failed to spawn ssh: {}", e)))?;
+
+        let tar = Command::new("tar")
+            .arg("-xpf-")
+            .arg("--numeric-owner")
+            .arg("--xattrs")
+            .arg("--xattrs-include=*")
+            .arg("--acls")
+            .arg("-C")
+            .arg(volume_path)
+            .stdin(Stdio::from(ssh.stdout.take().unwrap()))
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute tar: {}", e)))?;
+        let ssh = ssh.wait_with_output()?;
+
+        if !ssh.status.success() {
+            return Err(BackupError::new(
+                String::from_utf8_lossy(&ssh.stderr).trim(),
+            ));
+        }
+        if !tar.status.success() {
+            return Err(BackupError::new(
+                String::from_utf8_lossy(&tar.stderr).trim(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Quotes `value` as a single argument for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Backup and volume names follow the docker volume name rules, `[a-zA-Z0-9][a-zA-Z0-9_.-]*`,
+/// so they can't escape the quoting of a remote command or point outside the backup.
+fn check_name(kind: &str, name: &str) -> Result<(), BackupError> {
+    let valid = name
+        .chars()
+        .enumerate()
+        .all(|(i, c)| c.is_ascii_alphanumeric() || (i > 0 && matches!(c, '_' | '.' | '-')));
+    if name.is_empty() || !valid {
+        return Err(BackupError::new(&format!(
+            "Invalid {} name '{}'",
+            kind, name
+        )));
+    }
+    Ok(())
+}
+
+/// Kills a child started in its own process group together with everything it spawned.
+fn kill_process_group(child: &mut Child) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        let status = Command::new("kill")
+            .arg("-KILL")
+            .arg("--")
+            .arg(format!("-{}", child.id()))
+            .status()?;
+        if status.success() {
+            return Ok(());
+        }
+    }
+    child.kill()
+}
+
+/// Arguments: host, remote mkdir command, volume path, then pairs of volume name and the
+/// remote command that writes stdin to the volume archive.
+const ARCHIVE_SCRIPT: &str = r#"set -eo pipefail
+host="$1"
+ssh "$host" "$2" > /dev/null
+volume_path="$3"
+shift 3
+while [ "$#" -gt 0 ]; do
+    tar -cf- --numeric-owner --xattrs --acls -C "$volume_path" "$1" | ssh "$host" "$2"
+    shift 2
+done
+"#;
+
+impl ResticDestination {
+    fn local_path(&self) -> Option<&str> {
+        if let Some(path) = self.repository.strip_prefix("local:") {
+            Some(path)
+        } else if self.repository.starts_with('/') {
+            Some(&self.repository)
+        } else {
+            None
+        }
+    }
+
+    fn restic(&self) -> Command {
+        let mut restic = Command::new("restic");
+        restic.arg("-r").arg(&self.repository);
+        restic
+    }
+}
+
+impl BackupDestination for ResticDestination {
+    fn available_space(&self) -> Result<u64, BackupError> {
+        match self.local_path() {
+            Some(path) => LocalDestination {
+                path: path.to_owned(),
+            }
+            .available_space(),
+            //remote repositories don't report free space, skip the check
+            None => Ok(u64::MAX),
+        }
+    }
+
+    fn prepare(&self, _new_dir: &str) -> Result<(), BackupError> {
+        let output = self
+            .restic()
+            .arg("cat")
+            .arg("config")
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute restic: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(BackupError::new(&format!(
+                "Restic repository {} is not accessible: {}",
+                self.repository,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+
+    fn spawn_backup(
+        &self,
+        volume_path: &Path,
+        excluded_volumes: &[String],
+        new_dir: &str,
+    ) -> Result<Child, BackupError> {
+        let mut restic = self.restic();
+
+        restic.arg("backup").arg("--json");
+
+        //a bare name would also exclude matching directories inside other volumes
+        check_excluded_volumes(excluded_volumes, volume_path)?;
+        for volume in excluded_volumes {
+            restic.arg(format!("--exclude={}", volume_path.join(volume).display()));
+        }
+
+        restic
+            .arg("--tag")
+            .arg("dockerbackup")
+            .arg("--tag")
+            .arg(format!("run:{}", new_dir));
+        for volume in list_volumes(volume_path, excluded_volumes)? {
+            restic.arg("--tag").arg(format!("volume:{}", volume));
+        }
+
+        let exec_restic = restic
+            .arg(volume_path)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| BackupError::new(&format!("Failed to spawn restic: {}", e)))?;
+
+        Ok(exec_restic)
+    }
+
+    fn get_display_name(&self) -> String {
+        format!("restic:{}", self.repository)
+    }
+
+    fn parse_output_line(&self, line: &str) -> OutputLine {
+        let message: serde_json::Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => return OutputLine::Message(line.to_string()),
+        };
+
+        match message["message_type"].as_str() {
+            Some("status") => {
+                let percent_done = message["percent_done"].as_f64().unwrap_or(0.0);
+                let bytes_done = message["bytes_done"].as_u64().unwrap_or(0);
+                let total_bytes = message["total_bytes"].as_u64().unwrap_or(0);
+                OutputLine::Progress(format!(
+                    "{:.1}%, {} / {}",
+                    percent_done * 100.0,
+                    format_size(bytes_done),
+                    format_size(total_bytes)
+                ))
+            }
+            Some("error") => {
+                let error = message["error"]["message"]
+                    .as_str()
+                    .or(message["error"].as_str())
+                    .unwrap_or("unknown error");
+                match message["item"].as_str() {
+                    Some(item) => OutputLine::Message(format!("{}: {}", item, error)),
+                    None => OutputLine::Message(error.to_string()),
+                }
+            }
+            Some("exit_error") => OutputLine::Message(
+                message["message"]
+                    .as_str()
+                    .unwrap_or("restic exited with an error")
+                    .to_string(),
+            ),
+            _ => OutputLine::Ignore,
+        }
+    }
+
+    fn prune(&self, keep_last: u64) -> Result<(), BackupError> {
+        let output = self
+            .restic()
+            .arg("forget")
+            .arg("--tag")
+            .arg("dockerbackup")
+            .arg("--keep-last")
+            .arg(keep_last.to_string())
+            .arg("--prune")
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute restic: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(BackupError::new(&format!(
+                "restic forget failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl BorgDestination {
+    /// Splits `ssh://user@host[:port]/path` and scp-style `user@host:path` repositories
+    /// into host, port and remote path.
+    fn ssh_location(&self) -> Option<(&str, Option<&str>, String)> {
+        let location = match self.repository.strip_prefix("ssh://") {
+            Some(location) => location,
+            None => {
+                //scp-style paths are relative to the home directory unless they start with /
+                let (host, path) = self.repository.split_once(':')?;
+                if host.is_empty() || host.contains('/') {
+                    return None;
+                }
+                return Some((host, None, path.to_owned()));
+            }
+        };
+        let (authority, path) = location.split_at(location.find('/')?);
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+        //borg uses /./ and /~/ for paths relative to the home directory
+        let path = if let Some(relative) = path.strip_prefix("/./") {
+            relative.to_owned()
+        } else if path.starts_with("/~") {
+            path[1..].to_owned()
+        } else {
+            path.to_owned()
+        };
+        Some((host, port, path))
+    }
+
+    /// Archives are named `<prefix><new_dir>` so pruning leaves archives of other hosts
+    /// and tools in a shared repository alone.
+    fn archive_prefix(&self) -> String {
+        format!("dockerbackup-{}-", get_hostname())
+    }
+}
+
+impl BackupDestination for BorgDestination {
+    fn available_space(&self) -> Result<u64, BackupError> {
+        let (host, port, path) = match self.ssh_location() {
+            Some(location) => location,
+            None => {
+                return LocalDestination {
+                    path: self.repository.clone(),
+                }
+                .available_space()
+            }
+        };
+
+        let mut ssh = Command::new("ssh");
+        if let Some(port) = port {
+            ssh.arg("-p").arg(port);
+        }
+        let output = ssh
+            .arg(host)
+            .arg("df")
+            .arg("-B1")
+            .arg("--output=avail")
+            .arg(path)
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute ssh: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(BackupError::new(&format!(
+                "ssh df command failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        if lines.len() < 2 {
+            return Err(BackupError::new("Invalid df output"));
+        }
+
+        lines[1]
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| BackupError::new("Failed to parse available space"))
+    }
+
+    fn prepare(&self, new_dir: &str) -> Result<(), BackupError> {
+        let output = Command::new("borg")
+            .arg("list")
+            .arg("--short")
+            .arg(&self.repository)
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute borg: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(BackupError::new(&format!(
+                "Borg repository {} is not accessible: {}",
+                self.repository,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        let archive_name = format!("{}{}", self.archive_prefix(), new_dir);
+        if String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|archive| archive.trim() == archive_name)
+        {
+            return Err(BackupError::new("Archive already exists"));
+        }
+        Ok(())
+    }
+
+    fn spawn_backup(
+        &self,
+        volume_path: &Path,
+        excluded_volumes: &[String],
+        new_dir: &str,
+    ) -> Result<Child, BackupError> {
+        let mut borg = Command::new("borg");
+
+        borg.arg("create").arg("--log-json").arg("--progress");
+
+        check_excluded_volumes(excluded_volumes, volume_path)?;
+        for volume in excluded_volumes {
+            borg.arg(format!(
+                "--exclude=pp:{}",
+                volume_path.join(volume).display()
+            ));
+        }
+
+        let exec_borg = borg
+            .arg(format!(
+                "{}::{}{}",
+                self.repository,
+                self.archive_prefix(),
+                new_dir
+            ))
+            .arg(volume_path)
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| BackupError::new(&format!("Failed to spawn borg: {}", e)))?;
+
+        Ok(exec_borg)
+    }
+
+    fn get_display_name(&self) -> String {
+        format!("borg:{}", self.repository)
+    }
+
+    fn parse_output_line(&self, line: &str) -> OutputLine {
+        let message: serde_json::Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => return OutputLine::Message(line.to_string()),
+        };
+
+        match message["type"].as_str() {
+            Some("archive_progress") if !message["finished"].as_bool().unwrap_or(false) => {
+                OutputLine::Progress(format!(
+                    "{} files, {}",
+                    message["nfiles"].as_u64().unwrap_or(0),
+                    format_size(message["original_size"].as_u64().unwrap_or(0))
+                ))
+            }
+            Some("log_message") => match message["levelname"].as_str() {
+                Some("WARNING" | "ERROR" | "CRITICAL") => {
+                    OutputLine::Message(message["message"].as_str().unwrap_or_default().to_string())
+                }
+                _ => OutputLine::Ignore,
+            },
+            Some("file_status") if message["status"].as_str() == Some("E") => {
+                OutputLine::Message(format!(
+                    "Error reading {}",
+                    message["path"].as_str().unwrap_or_default()
+                ))
+            }
+            _ => OutputLine::Ignore,
+        }
+    }
+
+    fn prune(&self, keep_last: u64) -> Result<(), BackupError> {
+        //prune only marks the space as free, compact gives it back
+        for (command, args) in [
+            (
+                "prune",
+                vec![
+                    String::from("--keep-last"),
+                    keep_last.to_string(),
+                    String::from("--glob-archives"),
+                    format!("{}*", self.archive_prefix()),
+                ],
+            ),
+            ("compact", Vec::new()),
+        ] {
+            let output = Command::new("borg")
+                .arg(command)
+                .args(args)
+                .arg(&self.repository)
+                .output()
+                .map_err(|e| BackupError::new(&format!("Failed to execute borg: {}", e)))?;
+
+            if !output.status.success() {
+                return Err(BackupError::new(&format!(
+                    "borg {} failed: {}",
+                    command,
+                    String::from_utf8_lossy(&output.stderr)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RcloneDestination {
+    fn remote_dir(&self, new_dir: &str) -> String {
+        if self.remote.ends_with(':') || self.remote.ends_with('/') {
+            format!("{}{}", self.remote, new_dir)
+        } else {
+            format!("{}/{}", self.remote, new_dir)
+        }
+    }
+}
+
+impl BackupDestination for RcloneDestination {
+    fn available_space(&self) -> Result<u64, BackupError> {
+        let output = Command::new("rclone")
+            .arg("about")
+            .arg("--json")
+            .arg(&self.remote)
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute rclone: {}", e)))?;
+
+        if !output.status.success() {
+            return Err(BackupError::new(&format!(
+                "rclone about command failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        let about: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|_| BackupError::new("Invalid rclone about output"))?;
+
+        //not every backend reports free space, skip the check for those
+        Ok(about["free"].as_u64().unwrap_or(u64::MAX))
+    }
+
+    fn prepare(&self, new_dir: &str) -> Result<(), BackupError> {
+        let output = Command::new("rclone")
+            .arg("lsf")
+            .arg("--dirs-only")
+            .arg(&self.remote)
+            .output()
+            .map_err(|e| BackupError::new(&format!("Failed to execute rclone: {}", e)))?;
+
+        //lsf fails when the remote path doesn't exist yet, copy will create it
+        if output.status.success()
+            && String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .any(|dir| dir.trim_end_matches('/') == new_dir)
+        {
+            return Err(BackupError::new("Directory already exists"));
+        }
+        Ok(())
+    }
+
+    fn spawn_backup(
+        &self,
+        volume_path: &Path,
+        excluded_volumes: &[String],
+        new_dir: &str,
+    ) -> Result<Child, BackupError> {
+        let mut rclone = Command::new("rclone");
+
+        rclone
+            .arg("copy")
+            .arg("--use-json-log")
+            .arg("--stats=1s")
+            .arg("--stats-log-level=NOTICE");
+
+        check_excluded_volumes(excluded_volumes, volume_path)?;
+        for volume in excluded_volumes {
+            rclone
+                .arg(format!("--exclude=/{}", volume))
+                .arg(format!("--exclude=/{}/**", volume));
+        }
+
+        let exec_rclone = rclone
+            .arg(volume_path)
+            .arg(self.remote_dir(new_dir))
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| BackupError::new(&format!("Failed to spawn rclone: {}", e)))?;
+
+        Ok(exec_rclone)
+    }
+
+    fn store_run_log(&self, new_dir: &str, log: &str) -> Result<(), BackupError> {
+        let mut rclone = Command::new("rclone");
+        rclone
+            .arg("rcat")
+            .arg(format!("{}.log", self.remote_dir(new_dir)));
+        run_with_input(&mut rclone, log)
+    }
+
+    fn get_display_name(&self) -> String {
+        format!("rclone:{}", self.remote)
+    }
+
+    fn parse_output_line(&self, line: &str) -> OutputLine {
+        let message: serde_json::Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => return OutputLine::Message(line.to_string()),
+        };
+
+        if let Some(stats) = message.get("stats") {
+            let bytes = stats["bytes"].as_u64().unwrap_or(0);
+            let total_bytes = stats["totalBytes"].as_u64().unwrap_or(0);
+            let percent_done = if total_bytes > 0 {
+                bytes as f64 / total_bytes as f64 * 100.0
+            } else {
+                0.0
+            };
+            return OutputLine::Progress(format!(
+                "{:.1}%, {} / {}, {}/s",
+                percent_done,
+                format_size(bytes),
+                format_size(total_bytes),
+                format_size(stats["speed"].as_f64().unwrap_or(0.0) as u64)
+            ));
+        }
+
+        match message["level"].as_str() {
+            Some("error" | "critical") => OutputLine::Message(
+                message["msg"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+            ),
+            _ => OutputLine::Ignore,
+        }
+    }
+}
+
+const PLUGIN_PROTOCOL_VERSION: u64 = 1;
+
+impl PluginDestination {
+    fn request(
+        &self,
+        new_dir: Option<&str>,
+        volume_path: Option<&Path>,
+        excluded_volumes: &[String],
+        keep_last: Option<u64>,
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "protocol": PLUGIN_PROTOCOL_VERSION,
+            "url": self.url,
+            "new_dir": new_dir,
+            "volume_path": volume_path,
+            "excluded_volumes": excluded_volumes,
+            "keep_last": keep_last,
+        })
+    }
+
+    fn spawn(
+        &self,
+        operation: &str,
+        request: &serde_json::Value,
+        stdout: Stdio,
+    ) -> Result<Child, BackupError> {
+        let mut plugin = Command::new(&self.executable)
+            .arg(operation)
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                BackupError::new(&format!(
+                    "Failed to spawn plugin {}: {}",
+                    self.executable.display(),
+                    e
+                ))
+            })?;
+
+        if let Err(err) = write_stdin(&mut plugin, request.to_string().as_bytes()) {
+            let _ = plugin.kill();
+            let _ = plugin.wait();
+            return Err(err.into());
+        }
+        Ok(plugin)
+    }
+
+    fn call(&self, operation: &str, request: &serde_json::Value) -> Result<Output, BackupError> {
+        let output = self
+            .spawn(operation, request, Stdio::piped())?
+            .wait_with_output()?;
+
+        if !output.status.success() {
+            let error = serde_json::from_slice::<serde_json::Value>(&output.stdout)
+                .ok()
+                .and_then(|response| response["error"].as_str().map(|e| e.to_string()))
+                .unwrap_or_else(|| String::from_utf8_lossy(&output.stderr).trim().to_string());
+            return Err(BackupError::new(&format!(
+                "Plugin {} {} failed: {}",
+                self.executable.display(),
+                operation,
+                error
+            )));
+        }
+        Ok(output)
+    }
+}
+
+impl BackupDestination for PluginDestination {
+    fn available_space(&self) -> Result<u64, BackupError> {
+        let output = self.call("available-space", &self.request(None, None, &[], None))?;
+
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|_| BackupError::new("Invalid plugin available-space response"))?;
+
+        //plugins that can't tell the free space leave it out or return null
+        Ok(response["available_space"].as_u64().unwrap_or(u64::MAX))
+    }
+
+    fn prepare(&self, new_dir: &str) -> Result<(), BackupError> {
+        self.call("prepare", &self.request(Some(new_dir), None, &[], None))?;
+        Ok(())
+    }
+
+    fn spawn_backup(
+        &self,
+        volume_path: &Path,
+        excluded_volumes: &[String],
+        new_dir: &str,
+    ) -> Result<Child, BackupError> {
+        check_excluded_volumes(excluded_volumes, volume_path)?;
+
+        self.spawn(
+            "backup",
+            &self.request(Some(new_dir), Some(volume_path), excluded_volumes, None),
+            Stdio::piped(),
+        )
+    }
+
+    fn get_display_name(&self) -> String {
+        self.url.clone()
+    }
+
+    fn parse_output_line(&self, line: &str) -> OutputLine {
+        let message: serde_json::Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => return OutputLine::Message(line.to_string()),
+        };
+
+        if let Some(progress) = message["progress"].as_str() {
+            OutputLine::Progress(progress.to_string())
+        } else if let Some(error) = message["error"].as_str() {
+            OutputLine::Message(error.to_string())
+        } else {
+            OutputLine::Ignore
+        }
+    }
+
+    fn prune(&self, keep_last: u64) -> Result<(), BackupError> {
+        self.call("prune", &self.request(None, None, &[], Some(keep_last)))?;
+        Ok(())
+    }
+}
+
+/// Runs a command with `input` on stdin, failing with its stderr on a non-zero exit.
+fn run_with_input(command: &mut Command, input: &str) -> Result<(), BackupError> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let written = write_stdin(&mut child, input.as_bytes());
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(BackupError::new(
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ));
+    }
+    Ok(written?)
+}
+
+/// Writes `input` to the stdin of `child` and closes it to signal the end of input. A child that
+/// exits without reading everything is not an error here, its exit status tells what happened.
+fn write_stdin(child: &mut Child, input: &[u8]) -> io::Result<()> {
+    match child.stdin.take().map(|mut stdin| stdin.write_all(input)) {
+        Some(Err(err)) if err.kind() != io::ErrorKind::BrokenPipe => Err(err),
+        _ => Ok(()),
+    }
 }
 
 fn append_to_path(path: &str, new_dir: &str, target_os: &TargetOs) -> String {
@@ -226,6 +1235,17 @@ fn exclude_volumes(
     command: &mut Command,
     dirs_to_exclude: &[String],
     volume_path: &Path,
+) -> Result<(), BackupError> {
+    check_excluded_volumes(dirs_to_exclude, volume_path)?;
+    for volume in dirs_to_exclude {
+        command.arg(format!("--exclude={}", volume));
+    }
+    Ok(())
+}
+
+fn check_excluded_volumes(
+    dirs_to_exclude: &[String],
+    volume_path: &Path,
 ) -> Result<(), BackupError> {
     let volumes: HashSet<String> = fs::read_dir(volume_path)
         .map_err(|e| BackupError::new(&format!("Failed to read volume directory: {}", e)))?
@@ -240,7 +1260,21 @@ fn exclude_volumes(
                 volume
             )));
         }
-        command.arg(format!("--exclude={}", volume));
     }
     Ok(())
 }
+
+fn list_volumes(
+    volume_path: &Path,
+    excluded_volumes: &[String],
+) -> Result<Vec<String>, BackupError> {
+    let mut volumes: Vec<String> = fs::read_dir(volume_path)
+        .map_err(|e| BackupError::new(&format!("Failed to read volume directory: {}", e)))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(|s| s.to_string()))
+        .filter(|name| !excluded_volumes.contains(name))
+        .collect();
+    volumes.sort();
+    Ok(volumes)
+}
@@ -0,0 +1,80 @@
+use std::{thread, time::Duration};
+
+use crate::backup::logger::{LogLevel, Logger};
+
+//healthchecks.io keeps the first 100kB of a ping body, keep well below that
+const MAX_BODY_BYTES: usize = 10_000;
+const ATTEMPTS: u32 = 5;
+const INITIAL_DELAY: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum Ping {
+    Start,
+    Success,
+    Failure,
+}
+
+/// Dead man's switch pings in the healthchecks.io format: `<url>/start`, `<url>` and `<url>/fail`.
+pub struct Heartbeat {
+    pub url: String,
+}
+
+impl Heartbeat {
+    pub fn ping(&self, logger: &Logger, ping: Ping, body: Option<&str>) {
+        let base = self.url.trim_end_matches('/');
+        let url = match ping {
+            Ping::Start => format!("{}/start", base),
+            Ping::Success => base.to_string(),
+            Ping::Failure => format!("{}/fail", base),
+        };
+        let body = body.map(tail).unwrap_or_default();
+
+        let client = match reqwest::blocking::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+        {
+            Ok(client) => client,
+            Err(err) => {
+                logger.log(
+                    &format!("Failed to create heartbeat client: {}", err),
+                    LogLevel::Warning,
+                );
+                return;
+            }
+        };
+
+        //a missed ping makes the monitor alert, so retry for a while but never fail the backup
+        let mut delay = INITIAL_DELAY;
+        let mut last_error = String::new();
+        for attempt in 1..=ATTEMPTS {
+            match client.post(&url).body(body.clone()).send() {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => last_error = format!("status {}", response.status()),
+                Err(err) => last_error = err.to_string(),
+            }
+            if attempt < ATTEMPTS {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+        }
+        logger.log(
+            &format!(
+                "Failed to send heartbeat ping after {} attempts: {}",
+                ATTEMPTS, last_error
+            ),
+            LogLevel::Warning,
+        );
+    }
+}
+
+/// Keeps the end of the log, which is where errors usually are.
+fn tail(text: &str) -> String {
+    if text.len() <= MAX_BODY_BYTES {
+        return text.to_string();
+    }
+    let mut start = text.len() - MAX_BODY_BYTES;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("…{}", &text[start..])
+}
@@ -0,0 +1,122 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::backup::backup_result::BackupError;
+use crate::backup::logger::{LogFormat, LogRecord, LogSink};
+
+#[derive(Debug, Clone)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    pub max_size: u64,
+    pub max_age: Duration,
+    /// Number of rotated files kept as `<path>.1` to `<path>.<keep>`.
+    pub keep: u32,
+}
+
+struct OpenLog {
+    file: File,
+    size: u64,
+}
+
+/// Appends log records to a file, rotating it once it grows too large or too old.
+pub struct FileSink {
+    config: LogFileConfig,
+    log: Mutex<Option<OpenLog>>,
+}
+
+impl FileSink {
+    pub fn open(config: LogFileConfig) -> Result<Self, BackupError> {
+        if let Some(parent) = config.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        //age is checked once per run, a long backup doesn't rotate its own log midway
+        if let Ok(metadata) = fs::metadata(&config.path) {
+            let modified = metadata.created().or_else(|_| metadata.modified());
+            let age = modified
+                .ok()
+                .and_then(|time| SystemTime::now().duration_since(time).ok())
+                .unwrap_or_default();
+            if metadata.len() >= config.max_size || age >= config.max_age {
+                rotate(&config.path, config.keep)?;
+            }
+        }
+        let log = open_log(&config.path)?;
+        Ok(Self {
+            config,
+            log: Mutex::new(Some(log)),
+        })
+    }
+}
+
+impl LogSink for FileSink {
+    fn write(&self, record: &LogRecord) {
+        let line = match self.config.format {
+            LogFormat::Text => format!("{}\n", record.to_text()),
+            LogFormat::Json => format!("{}\n", record.to_json()),
+        };
+        let mut log = self.log.lock().unwrap();
+        if log
+            .as_ref()
+            .is_some_and(|log| log.size > 0 && log.size + line.len() as u64 > self.config.max_size)
+        {
+            //close the file before renaming it, windows can't rename open files
+            *log = None;
+            let _ = rotate(&self.config.path, self.config.keep);
+        }
+        if log.is_none() {
+            *log = open_log(&self.config.path).ok();
+        }
+        //logging must never stop a backup, write errors are dropped
+        if let Some(log) = log.as_mut() {
+            if log.file.write_all(line.as_bytes()).is_ok() {
+                log.size += line.len() as u64;
+            }
+        }
+    }
+}
+
+fn open_log(path: &Path) -> Result<OpenLog, BackupError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| {
+            BackupError::new(&format!(
+                "Failed to open log file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+    let size = file.metadata()?.len();
+    Ok(OpenLog { file, size })
+}
+
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
+
+fn rotate(path: &Path, keep: u32) -> Result<(), BackupError> {
+    if keep == 0 {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+    let _ = fs::remove_file(rotated_path(path, keep));
+    for index in (1..keep).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+    fs::rename(path, rotated_path(path, 1))?;
+    Ok(())
+}
@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     io::{Stdout, Write},
     sync::Mutex,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
@@ -10,6 +12,10 @@ use crossterm::{
     terminal::{self, ClearType},
 };
 
+//how often plain output repeats the progress of a running backup
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy)]
 pub enum LogLevel {
     Info,
     Warning,
@@ -17,37 +23,292 @@ pub enum LogLevel {
     Success,
 }
 
+impl LogLevel {
+    fn color(&self) -> Color {
+        match self {
+            LogLevel::Info => Color::Cyan,
+            LogLevel::Warning => Color::Yellow,
+            LogLevel::Error => Color::Red,
+            LogLevel::Success => Color::Green,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "INFO",
+            LogLevel::Warning => "WARN",
+            LogLevel::Error => "ERROR",
+            LogLevel::Success => "OK",
+        }
+    }
+
+    /// RFC 5424 severity, also used as the journald priority.
+    pub fn syslog_severity(&self) -> u8 {
+        match self {
+            LogLevel::Error => 3,
+            LogLevel::Warning => 4,
+            LogLevel::Success => 5,
+            LogLevel::Info => 6,
+        }
+    }
+
+    fn json_name(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Warning => "warning",
+            LogLevel::Error => "error",
+            LogLevel::Success => "success",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    Text,
+    /// One JSON object per line for every log message and lifecycle event.
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_str(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unsupported log format {}", format)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn from_str(mode: &str) -> Result<Self, String> {
+        match mode.to_lowercase().as_str() {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("Unsupported color mode {}", mode)),
+        }
+    }
+}
+
+/// A single log message or lifecycle event passed to every sink.
+pub struct LogRecord<'a> {
+    pub timestamp: chrono::DateTime<chrono::Local>,
+    pub level: LogLevel,
+    pub event: &'a str,
+    pub message: Option<&'a str>,
+    pub destination: Option<&'a str>,
+    pub fields: &'a serde_json::Value,
+}
+
+impl<'a> LogRecord<'a> {
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut line = serde_json::json!({
+            "timestamp": self.timestamp.to_rfc3339(),
+            "level": self.level.json_name(),
+            "event": self.event,
+        });
+        if let Some(message) = self.message {
+            line["message"] = message.into();
+        }
+        if let Some(destination) = self.destination {
+            line["destination"] = destination.into();
+        }
+        if !self.fields.is_null() {
+            line["fields"] = self.fields.clone();
+        }
+        line
+    }
+
+    /// Plain text line, events without a message are written as their name and fields.
+    pub fn to_text(&self) -> String {
+        format!(
+            "[{}] {:<5} {}",
+            self.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            self.level.name(),
+            self.text()
+        )
+    }
+
+    /// The message, or the event name, destination and fields for events.
+    pub fn text(&self) -> String {
+        match self.message {
+            Some(message) => message.to_string(),
+            None => {
+                let mut text = self.event.to_string();
+                if let Some(destination) = self.destination {
+                    text.push_str(&format!(" {}", destination));
+                }
+                if !self.fields.is_null() {
+                    text.push_str(&format!(" {}", self.fields));
+                }
+                text
+            }
+        }
+    }
+}
+
+/// Additional log output next to the terminal, e.g. a log file.
+pub trait LogSink: Send + Sync {
+    fn write(&self, record: &LogRecord);
+}
+
 pub struct Logger {
     stdout: Mutex<Stdout>,
+    history: Mutex<Vec<String>>,
+    /// Line-oriented output without cursor movement, for cron mails, journald and CI logs.
+    plain: bool,
+    color: bool,
+    format: LogFormat,
+    sinks: Vec<Box<dyn LogSink>>,
+    last_timer: Mutex<HashMap<usize, Instant>>,
+    last_progress: Mutex<HashMap<usize, Instant>>,
 }
 
 impl Logger {
-    pub fn new(stdout: Stdout) -> Self {
+    pub fn new(stdout: Stdout, plain: bool, color: ColorMode, format: LogFormat) -> Self {
+        let json = format == LogFormat::Json;
         Self {
             stdout: Mutex::new(stdout),
+            history: Mutex::new(Vec::new()),
+            plain: plain || json,
+            color: match color {
+                _ if json => false,
+                ColorMode::Always => true,
+                ColorMode::Never => false,
+                ColorMode::Auto => !plain && std::env::var_os("NO_COLOR").is_none(),
+            },
+            format,
+            sinks: Vec::new(),
+            last_timer: Mutex::new(HashMap::new()),
+            last_progress: Mutex::new(HashMap::new()),
         }
     }
 
+    pub fn with_sink(mut self, sink: Box<dyn LogSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
     pub fn log(&self, message: &str, level: LogLevel) {
-        let color = match level {
-            LogLevel::Info => Color::Cyan,
-            LogLevel::Warning => Color::Yellow,
-            LogLevel::Error => Color::Red,
-            LogLevel::Success => Color::Green,
+        let record = LogRecord {
+            timestamp: chrono::Local::now(),
+            level,
+            event: "log",
+            message: Some(message),
+            destination: None,
+            fields: &serde_json::Value::Null,
         };
+        self.history.lock().unwrap().push(format!(
+            "[{}] {}",
+            record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            message
+        ));
+        if self.format == LogFormat::Json {
+            self.print_json(&record);
+        } else if self.plain {
+            self.print(&format!("{}\n", record.to_text()), level);
+        } else {
+            self.print(&format!("{}\n", message), level);
+        }
+        self.write_sinks(&record);
+    }
+
+    fn write_sinks(&self, record: &LogRecord) {
+        for sink in &self.sinks {
+            sink.write(record);
+        }
+    }
+
+    fn print(&self, text: &str, level: LogLevel) {
         let mut stdout = self.stdout.lock().unwrap();
-        execute!(
-            stdout,
-            SetForegroundColor(color),
-            Print(message),
-            Print("\n"),
-            ResetColor
-        )
-        .unwrap();
+        if self.color {
+            execute!(
+                stdout,
+                SetForegroundColor(level.color()),
+                Print(text),
+                ResetColor
+            )
+            .unwrap();
+        } else {
+            execute!(stdout, Print(text)).unwrap();
+        }
+        stdout.flush().unwrap();
+    }
+
+    /// Records a lifecycle event. The terminal only shows events in the JSON format,
+    /// other sinks always receive them.
+    pub fn event(
+        &self,
+        level: LogLevel,
+        event: &str,
+        destination: Option<&str>,
+        fields: serde_json::Value,
+    ) {
+        let record = LogRecord {
+            timestamp: chrono::Local::now(),
+            level,
+            event,
+            message: None,
+            destination,
+            fields: &fields,
+        };
+        if self.format == LogFormat::Json {
+            self.print_json(&record);
+        }
+        self.write_sinks(&record);
+    }
+
+    /// Progress events are rate limited like the plain output timers.
+    pub fn progress(&self, timer_id: usize, destination: &str, fields: serde_json::Value) {
+        if (self.format == LogFormat::Json || !self.sinks.is_empty())
+            && is_due(&self.last_progress, timer_id, LogLevel::Info)
+        {
+            self.event(LogLevel::Info, "progress", Some(destination), fields);
+        }
+    }
+
+    fn print_json(&self, record: &LogRecord) {
+        let mut stdout = self.stdout.lock().unwrap();
+        writeln!(stdout, "{}", record.to_json()).unwrap();
         stdout.flush().unwrap();
     }
 
-    pub fn log_elapsed_time(&self, timer_id: usize, message: &str, color: Color) {
+    pub fn history(&self) -> String {
+        self.history.lock().unwrap().join("\n")
+    }
+
+    pub fn log_elapsed_time(&self, timer_id: usize, message: &str, level: LogLevel) {
+        if self.format == LogFormat::Json {
+            return;
+        }
+        if self.plain {
+            if !is_due(&self.last_timer, timer_id, level) {
+                return;
+            }
+            self.print(
+                &format!(
+                    "[{}] {:<5} {}\n",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    level.name(),
+                    message.trim_start_matches('\r')
+                ),
+                level,
+            );
+            return;
+        }
+
+        //a wrapped line would shift every timer below it, so cut it at the terminal width
+        let message: String = match terminal::size() {
+            Ok((columns, _)) if columns > 0 => message.chars().take(columns as usize - 1).collect(),
+            _ => message.to_string(),
+        };
         let mut stdout = self.stdout.lock().unwrap();
 
         execute!(
@@ -56,8 +317,12 @@ impl Logger {
             cursor::MoveDown(timer_id as u16 + 1),
             cursor::MoveToColumn(0),
             terminal::Clear(ClearType::CurrentLine),
-            SetForegroundColor(color),
-            Print(message),
+            SetForegroundColor(if self.color {
+                level.color()
+            } else {
+                Color::Reset
+            }),
+            Print(&message),
             ResetColor,
             cursor::RestorePosition,
         )
@@ -67,6 +332,9 @@ impl Logger {
     }
 
     pub fn reset_cursor_after_timers(&self, active_timers: u16) {
+        if self.plain {
+            return;
+        }
         let mut stdout = self.stdout.lock().unwrap();
         execute!(
             stdout,
@@ -80,6 +348,9 @@ impl Logger {
     }
 
     pub fn clear_terminal(&self) {
+        if self.plain {
+            return;
+        }
         let mut stdout = self.stdout.lock().unwrap();
         execute!(
             stdout,
@@ -92,14 +363,37 @@ impl Logger {
     }
 
     pub fn hide_cursor(&self) {
+        if self.plain {
+            return;
+        }
         let mut stdout = self.stdout.lock().unwrap();
         execute!(stdout, Hide).unwrap();
         stdout.flush().unwrap();
     }
 
     pub fn show_cursor(&self) {
+        if self.plain {
+            return;
+        }
         let mut stdout = self.stdout.lock().unwrap();
         execute!(stdout, Show).unwrap();
         stdout.flush().unwrap();
     }
 }
+
+//running timers are repeated periodically, final results are always printed
+fn is_due(last: &Mutex<HashMap<usize, Instant>>, timer_id: usize, level: LogLevel) -> bool {
+    let mut last = last.lock().unwrap();
+    if matches!(level, LogLevel::Info) {
+        if last
+            .get(&timer_id)
+            .is_some_and(|last| last.elapsed() < PLAIN_PROGRESS_INTERVAL)
+        {
+            return false;
+        }
+        last.insert(timer_id, Instant::now());
+    } else {
+        last.remove(&timer_id);
+    }
+    true
+}
@@ -0,0 +1,267 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backup::backup_result::{BackupError, RunSummary};
+
+const PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Values that have to survive between runs, e.g. the last success of a destination that failed today.
+#[derive(Serialize, Deserialize, Default)]
+pub struct MetricsState {
+    last_run_success: Option<i64>,
+    last_success: HashMap<String, i64>,
+    consecutive_failures: HashMap<String, u64>,
+}
+
+#[derive(Clone)]
+pub struct MetricsConfig {
+    /// File read by the node_exporter textfile collector, should end with `.prom`.
+    pub textfile: Option<PathBuf>,
+    pub pushgateway: Option<String>,
+}
+
+impl MetricsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.textfile.is_some() || self.pushgateway.is_some()
+    }
+}
+
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    samples: Vec<(Option<(&'static str, String)>, String)>,
+}
+
+impl Metric {
+    fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            samples: Vec::new(),
+        }
+    }
+
+    fn sample(mut self, value: impl ToString) -> Self {
+        self.samples.push((None, value.to_string()));
+        self
+    }
+
+    fn labeled(&mut self, label: &'static str, label_value: &str, value: impl ToString) {
+        self.samples
+            .push((Some((label, label_value.to_string())), value.to_string()));
+    }
+}
+
+/// Renders the metrics of a finished run in the Prometheus text format and updates the state.
+pub fn render_metrics(
+    summary: &RunSummary,
+    success: bool,
+    destinations: &[String],
+    volume_sizes: &BTreeMap<String, u64>,
+    state: &mut MetricsState,
+) -> String {
+    let now = chrono::Utc::now().timestamp();
+    if success {
+        state.last_run_success = Some(now);
+    }
+
+    let mut destination_success = Metric::new(
+        "dockerbackup_destination_success",
+        "Whether the last backup to the destination succeeded.",
+    );
+    let mut last_success = Metric::new(
+        "dockerbackup_destination_last_success_timestamp_seconds",
+        "Unix time of the last successful backup to the destination.",
+    );
+    let mut consecutive_failures = Metric::new(
+        "dockerbackup_destination_consecutive_failures",
+        "Number of failed runs since the last successful backup to the destination.",
+    );
+    let mut duration = Metric::new(
+        "dockerbackup_destination_duration_seconds",
+        "Duration of the last successful backup to the destination.",
+    );
+    let mut transferred = Metric::new(
+        "dockerbackup_destination_transferred_bytes",
+        "Bytes transferred by the last successful backup to the destination.",
+    );
+    for destination in destinations {
+        let result = summary.results.iter().find(|result| match result {
+            Ok(success) => &success.destination == destination,
+            Err(err) => err.destination.as_ref() == Some(destination),
+        });
+        //destinations without a result were never started, e.g. because the volume size failed
+        let succeeded = match result {
+            Some(Ok(success)) => {
+                duration.labeled("destination", destination, success.duration.as_secs_f64());
+                //only destinations that count what they send report it
+                if let Some(bytes) = success.transferred {
+                    transferred.labeled("destination", destination, bytes);
+                }
+                state.last_success.insert(destination.clone(), now);
+                state.consecutive_failures.remove(destination);
+                true
+            }
+            _ => {
+                *state
+                    .consecutive_failures
+                    .entry(destination.clone())
+                    .or_default() += 1;
+                false
+            }
+        };
+        destination_success.labeled("destination", destination, succeeded as u8);
+        if let Some(timestamp) = state.last_success.get(destination) {
+            last_success.labeled("destination", destination, timestamp);
+        }
+        consecutive_failures.labeled(
+            "destination",
+            destination,
+            state.consecutive_failures.get(destination).unwrap_or(&0),
+        );
+    }
+    //forget destinations that were removed from the command line
+    state
+        .last_success
+        .retain(|destination, _| destinations.contains(destination));
+    state
+        .consecutive_failures
+        .retain(|destination, _| destinations.contains(destination));
+
+    let mut volume_size = Metric::new(
+        "dockerbackup_volume_size_bytes",
+        "Size of the volume at the time of the last run.",
+    );
+    for (volume, size) in volume_sizes {
+        volume_size.labeled("volume", volume, size);
+    }
+
+    let mut metrics = vec![
+        Metric::new(
+            "dockerbackup_last_run_timestamp_seconds",
+            "Unix time of the last run.",
+        )
+        .sample(now),
+        Metric::new(
+            "dockerbackup_last_run_success",
+            "Whether the last run succeeded.",
+        )
+        .sample(success as u8),
+        Metric::new(
+            "dockerbackup_last_run_duration_seconds",
+            "Duration of the last run.",
+        )
+        .sample(summary.duration.as_secs_f64()),
+        Metric::new(
+            "dockerbackup_last_run_failures",
+            "Number of failed destinations in the last run.",
+        )
+        .sample(summary.results.iter().filter(|r| r.is_err()).count()),
+        Metric::new(
+            "dockerbackup_containers_stopped",
+            "Number of containers stopped during the last run.",
+        )
+        .sample(summary.stopped_containers.len()),
+        Metric::new(
+            "dockerbackup_containers_restart_failed",
+            "Number of stopped containers that failed to start again in the last run.",
+        )
+        .sample(summary.stopped_containers.len() - summary.restarted_containers.len()),
+    ];
+    if let Some(timestamp) = state.last_run_success {
+        metrics.push(
+            Metric::new(
+                "dockerbackup_last_success_timestamp_seconds",
+                "Unix time of the last successful run.",
+            )
+            .sample(timestamp),
+        );
+    }
+    metrics.extend([
+        destination_success,
+        last_success,
+        consecutive_failures,
+        duration,
+        transferred,
+        volume_size,
+    ]);
+
+    let mut text = String::new();
+    for metric in metrics.iter().filter(|metric| !metric.samples.is_empty()) {
+        writeln!(text, "# HELP {} {}", metric.name, metric.help).unwrap();
+        writeln!(text, "# TYPE {} gauge", metric.name).unwrap();
+        for (label, value) in &metric.samples {
+            match label {
+                Some((label, label_value)) => writeln!(
+                    text,
+                    "{}{{{}=\"{}\"}} {}",
+                    metric.name,
+                    label,
+                    escape_label(label_value),
+                    value
+                ),
+                None => writeln!(text, "{} {}", metric.name, value),
+            }
+            .unwrap();
+        }
+    }
+    text
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes next to the target and renames, so the collector never reads a partial file.
+pub fn write_textfile(path: &Path, metrics: &str) -> Result<(), BackupError> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    fs::write(&tmp_path, metrics).map_err(|e| {
+        BackupError::new(&format!(
+            "Failed to write metrics to {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    fs::rename(&tmp_path, path).inspect_err(|_| {
+        let _ = fs::remove_file(&tmp_path);
+    })?;
+    Ok(())
+}
+
+/// Replaces the metrics of the `dockerbackup` job for this host on the Pushgateway.
+pub fn push_metrics(url: &str, hostname: &str, metrics: &str) -> Result<(), BackupError> {
+    let url = format!(
+        "{}/metrics/job/dockerbackup/instance/{}",
+        url.trim_end_matches('/'),
+        hostname
+    );
+    let response = reqwest::blocking::Client::builder()
+        .timeout(PUSH_TIMEOUT)
+        .build()
+        .and_then(|client| {
+            client
+                .put(&url)
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(metrics.to_string())
+                .send()
+        })
+        .map_err(|e| BackupError::new(&format!("Failed to push metrics: {}", e)))?;
+    if !response.status().is_success() {
+        return Err(BackupError::new(&format!(
+            "Pushgateway responded with status {}",
+            response.status()
+        )));
+    }
+    Ok(())
+}
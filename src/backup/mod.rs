@@ -1,33 +1,70 @@
-use backup_result::{BackupError, BackupSuccess};
+pub use backup_result::RunStatus;
+use backup_result::{BackupError, BackupSuccess, RunSummary};
 use chrono::{self, Datelike};
 use clap::builder::styling::{AnsiColor, Effects, Styles};
 use clap::ArgAction;
-use crossterm::style::Color;
-use std::collections::HashSet;
-use std::io::{stdout, BufReader, Read};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::io::{stdout, IsTerminal};
+use std::path::{Path, PathBuf};
 use std::process::{exit, Child};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use utils::{
-    check_docker, check_running_containers, get_elapsed_time, get_volumes_size, handle_containers,
-    parse_destination_path,
+    check_docker, check_running_containers, format_progress, format_size, get_elapsed_time,
+    get_hostname, get_volumes_size, handle_containers, parse_destination_path,
+    parse_gotify_priority, parse_min_severity, parse_notify_on, parse_quiet_hours,
+    parse_secret_file, parse_webhook_config, watch_output,
 };
 
-use crate::backup::destination::BackupDestination;
-use crate::backup::logger::{LogLevel, Logger};
+use crate::backup::destination::{BackupDestination, Progress};
+use crate::backup::heartbeat::{Heartbeat, Ping};
+use crate::backup::log_file::{FileSink, LogFileConfig};
+use crate::backup::logger::{ColorMode, LogFormat, LogLevel, Logger};
+use crate::backup::metrics::{
+    push_metrics, render_metrics, write_textfile, MetricsConfig, MetricsState,
+};
+use crate::backup::notification::{
+    save_delayed_notifications, send_delayed_notifications, DelayedNotification, GotifyConfig,
+    MatrixConfig, NotificationEvent, NotificationMode, NotificationRule, NtfyConfig, QuietHours,
+    RetryPolicy, Severity, TelegramConfig, WebhookConfig,
+};
+use crate::backup::report::write_report;
+use crate::backup::run_history::{runs_table, status_table, RunHistory};
+use crate::backup::size_history::{check_volume_sizes, SizeThresholds};
+use crate::backup::smtp::{SmtpConfig, SmtpSecurity};
+use crate::backup::state::{read_state, write_state};
+use crate::backup::summary_file::write_summary_file;
+#[cfg(unix)]
+use crate::backup::syslog::JournaldSink;
+use crate::backup::syslog::{parse_syslog_facility, SyslogSink};
 
 mod backup_result;
 mod destination;
+mod heartbeat;
+mod log_file;
 mod logger;
+mod metrics;
 mod notification;
+mod report;
+mod run_history;
+mod size_history;
+mod smtp;
+mod state;
+mod summary_file;
+mod syslog;
 mod utils;
 
+const NOTIFICATION_STATE: &str = "notification_state.json";
+const METRICS_STATE: &str = "metrics_state.json";
+
+type BackupHandles = Vec<(Arc<Mutex<Child>>, String, Arc<dyn BackupDestination>)>;
+
 type BackupChannel = (
-    mpsc::Sender<Result<String, BackupError>>,
-    mpsc::Receiver<Result<String, BackupError>>,
+    mpsc::Sender<Result<BackupSuccess, BackupError>>,
+    mpsc::Receiver<Result<BackupSuccess, BackupError>>,
 );
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -54,16 +91,39 @@ pub struct DockerBackup {
     volume_path: PathBuf,
     excluded_containers: Vec<String>,
     excluded_volumes: Vec<String>,
-    gotify_url: Option<String>,
+    gotify: Option<GotifyConfig>,
     discord_url: Option<String>,
-    receiver: Option<Receiver<Result<String, BackupError>>>,
-    sender: Option<Sender<Result<String, BackupError>>>,
+    slack_url: Option<String>,
+    teams_url: Option<String>,
+    keep_last: Option<u64>,
+    heartbeat: Option<Heartbeat>,
+    ntfy: Option<NtfyConfig>,
+    telegram: Option<TelegramConfig>,
+    matrix: Option<MatrixConfig>,
+    webhooks: Vec<WebhookConfig>,
+    smtp: Option<SmtpConfig>,
+    notification_mode: NotificationMode,
+    notification_rules: HashMap<String, NotificationRule>,
+    delayed_notifications: Mutex<Vec<DelayedNotification>>,
+    previous_results: HashMap<String, bool>,
+    state_dir: PathBuf,
+    store_run_log: bool,
+    metrics: MetricsConfig,
+    summary_json: Option<PathBuf>,
+    report_dir: Option<PathBuf>,
+    report: Option<String>,
+    size_thresholds: SizeThresholds,
+    /// Sizes of the backed up volumes, empty until they were measured in this run.
+    volume_sizes: Mutex<Option<BTreeMap<String, u64>>>,
+    /// Unusual volume sizes found before the destinations were started.
+    size_anomalies: Mutex<Vec<String>>,
+    receiver: Option<Receiver<Result<BackupSuccess, BackupError>>>,
+    sender: Option<Sender<Result<BackupSuccess, BackupError>>>,
     logger: Arc<Logger>,
 }
 
 impl DockerBackup {
     pub fn build() -> DockerBackup {
-        check_docker().expect("Can't continue without Docker installed");
         let date = chrono::Local::now();
         let new_dir = format!("{}-{}-{}", date.year(), date.month(), date.day());
 
@@ -76,7 +136,7 @@ impl DockerBackup {
             .usage(AnsiColor::Yellow.on_default() | Effects::BOLD)
             .placeholder(AnsiColor::Yellow.on_default()))
             .arg(clap::Arg::new("dest_path")
-                .help("Backup destination path. This argument can be used multiple times and each path must be in the following format: [/backup or user@host:/backup, windows]. Target os must be specified with ssh paths.")
+                .help("Backup destination path. This argument can be used multiple times and each path must be in the following format: [/backup, user@host:/backup,windows, restic:<repository>, borg:<repository>, rclone:<remote>:<path> or <scheme>://<path> handled by a dockerbackup-dest-<scheme> plugin]. Target os must be specified with ssh paths.")
                 .required(true)
                 .num_args(1..)
                 .action(ArgAction::Append)
@@ -104,12 +164,350 @@ impl DockerBackup {
                 .required(false)
                 .short('g')
                 .long("gotify"))
+            .arg(clap::Arg::new("gotify_token_file")
+                .help("File containing the Gotify application token. The GOTIFY_TOKEN environment variable is used when not set")
+                .required(false)
+                .requires("gotify_url")
+                .value_parser(parse_secret_file)
+                .long("gotify-token-file"))
+            .arg(clap::Arg::new("gotify_priority")
+                .help("Gotify priority for an event in the format event=priority, e.g. failure=10. Defaults to success=2, recovery=4, warning=5, failure=8. This argument can be used multiple times")
+                .required(false)
+                .requires("gotify_url")
+                .action(ArgAction::Append)
+                .value_parser(parse_gotify_priority)
+                .long("gotify-priority"))
+            .arg(clap::Arg::new("gotify_retries")
+                .help("Number of attempts for sending a Gotify notification")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .default_value("5")
+                .required(false)
+                .long("gotify-retries"))
+            .arg(clap::Arg::new("gotify_retry_delay")
+                .help("Seconds to wait before retrying a Gotify notification. The delay doubles after every attempt")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("2")
+                .required(false)
+                .long("gotify-retry-delay"))
+            .arg(clap::Arg::new("gotify_deadline")
+                .help("Maximum number of seconds spent retrying a Gotify notification")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("60")
+                .required(false)
+                .long("gotify-deadline"))
             .arg(clap::Arg::new("discord_url")
                 .help("Discord webhook url for notifications")
                 .required(false)
                 .long("discord"))
+            .arg(clap::Arg::new("slack_url")
+                .help("Slack incoming webhook url for notifications")
+                .required(false)
+                .long("slack"))
+            .arg(clap::Arg::new("teams_url")
+                .help("Microsoft Teams incoming webhook url for notifications")
+                .required(false)
+                .long("teams"))
+            .arg(clap::Arg::new("ntfy_url")
+                .help("ntfy topic url for notifications")
+                .required(false)
+                .long("ntfy"))
+            .arg(clap::Arg::new("ntfy_token")
+                .help("ntfy access token")
+                .required(false)
+                .requires("ntfy_url")
+                .long("ntfy-token"))
+            .arg(clap::Arg::new("ntfy_tags")
+                .help("Additional ntfy tags or emojis")
+                .required(false)
+                .requires("ntfy_url")
+                .num_args(1..)
+                .long("ntfy-tags"))
+            .arg(clap::Arg::new("ntfy_click")
+                .help("Url opened when the ntfy notification is clicked")
+                .required(false)
+                .requires("ntfy_url")
+                .long("ntfy-click"))
+            .arg(clap::Arg::new("telegram_token")
+                .help("Telegram bot token for notifications")
+                .required(false)
+                .requires("telegram_chat")
+                .long("telegram-token"))
+            .arg(clap::Arg::new("telegram_chat")
+                .help("Telegram chat id for notifications")
+                .required(false)
+                .requires("telegram_token")
+                .long("telegram-chat"))
+            .arg(clap::Arg::new("telegram_api")
+                .help("Telegram Bot API server url, e.g. a self-hosted server")
+                .default_value("https://api.telegram.org")
+                .required(false)
+                .long("telegram-api"))
+            .arg(clap::Arg::new("matrix_homeserver")
+                .help("Matrix homeserver url for notifications")
+                .required(false)
+                .requires_all(["matrix_token", "matrix_room"])
+                .long("matrix-homeserver"))
+            .arg(clap::Arg::new("matrix_token")
+                .help("Matrix access token")
+                .required(false)
+                .requires("matrix_homeserver")
+                .long("matrix-token"))
+            .arg(clap::Arg::new("matrix_room")
+                .help("Matrix room id for notifications")
+                .required(false)
+                .requires("matrix_homeserver")
+                .long("matrix-room"))
+            .arg(clap::Arg::new("smtp_host")
+                .help("SMTP server host for email notifications")
+                .required(false)
+                .requires_all(["smtp_from", "smtp_to"])
+                .long("smtp-host"))
+            .arg(clap::Arg::new("smtp_port")
+                .help("SMTP server port [default: 587 for starttls, 465 for tls, 25 for none]")
+                .value_parser(clap::value_parser!(u16))
+                .required(false)
+                .requires("smtp_host")
+                .long("smtp-port"))
+            .arg(clap::Arg::new("smtp_security")
+                .help("SMTP connection security")
+                .value_parser(["starttls", "tls", "none"])
+                .default_value("starttls")
+                .required(false)
+                .long("smtp-security"))
+            .arg(clap::Arg::new("smtp_user")
+                .help("SMTP username")
+                .required(false)
+                .requires("smtp_host")
+                .long("smtp-user"))
+            .arg(clap::Arg::new("smtp_password")
+                .help("SMTP password")
+                .required(false)
+                .requires("smtp_user")
+                .long("smtp-password"))
+            .arg(clap::Arg::new("smtp_from")
+                .help("Sender address for email notifications")
+                .required(false)
+                .requires("smtp_host")
+                .long("smtp-from"))
+            .arg(clap::Arg::new("smtp_to")
+                .help("Recipient addresses for email notifications")
+                .required(false)
+                .requires("smtp_host")
+                .num_args(1..)
+                .long("smtp-to"))
+            .arg(clap::Arg::new("smtp_attach_log")
+                .help("Attach the run log to email notifications")
+                .required(false)
+                .requires("smtp_host")
+                .action(ArgAction::SetTrue)
+                .long("smtp-attach-log"))
+            .arg(clap::Arg::new("smtp_attach_report")
+                .help("Attach the HTML report of the run to summary email notifications")
+                .required(false)
+                .requires_all(["smtp_host", "report_dir"])
+                .action(ArgAction::SetTrue)
+                .long("smtp-attach-report"))
+            .arg(clap::Arg::new("webhook")
+                .help("Path to a JSON webhook config with url, method, headers and a body template for notifications. This argument can be used multiple times")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(parse_webhook_config)
+                .long("webhook"))
+            .arg(clap::Arg::new("notification_mode")
+                .help("Send one summary notification per run or one notification per destination")
+                .value_parser(["summary", "per-destination"])
+                .default_value("summary")
+                .required(false)
+                .long("notification-mode"))
+            .arg(clap::Arg::new("notify_on")
+                .help("Events that trigger a notifier, in the format notifier=event,... with events success, warning, failure and recovery. Use all as the notifier to apply to every notifier. This argument can be used multiple times")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(parse_notify_on)
+                .long("notify-on"))
+            .arg(clap::Arg::new("notify_min_severity")
+                .help("Minimum severity for a notifier, in the format notifier=info|warning|critical. This argument can be used multiple times")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(parse_min_severity)
+                .long("notify-min-severity"))
+            .arg(clap::Arg::new("quiet_hours")
+                .help("Quiet hours for a notifier in the format notifier=HH:MM-HH:MM. Non-critical notifications are saved and sent by the first run after the window ends. This argument can be used multiple times")
+                .required(false)
+                .action(ArgAction::Append)
+                .value_parser(parse_quiet_hours)
+                .long("quiet-hours"))
+            .arg(clap::Arg::new("plain")
+                .help("Line-oriented output with timestamps and periodic progress instead of live timers. Used automatically when stdout is not a terminal")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .visible_alias("no-tty")
+                .long("plain"))
+            .arg(clap::Arg::new("log_format")
+                .help("Output format: text or json. Json prints one object per line for every message and lifecycle event")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .required(false)
+                .long("log-format"))
+            .arg(clap::Arg::new("log_file")
+                .help("Also write the log to this file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .required(false)
+                .long("log-file"))
+            .arg(clap::Arg::new("log_file_format")
+                .help("Format of the log file: text or json. Defaults to --log-format")
+                .value_parser(["text", "json"])
+                .required(false)
+                .requires("log_file")
+                .long("log-file-format"))
+            .arg(clap::Arg::new("log_max_size")
+                .help("Rotate the log file once it grows beyond this many megabytes")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("10")
+                .required(false)
+                .long("log-max-size"))
+            .arg(clap::Arg::new("log_max_age")
+                .help("Rotate the log file at the start of a run once it is older than this many days")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("7")
+                .required(false)
+                .long("log-max-age"))
+            .arg(clap::Arg::new("log_keep")
+                .help("Number of rotated log files to keep")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("5")
+                .required(false)
+                .long("log-keep"))
+            .arg(clap::Arg::new("store_run_log")
+                .help("Store the log of each run as <date>.log next to the backup on local, unix ssh and rclone destinations")
+                .required(false)
+                .action(ArgAction::SetTrue)
+                .long("store-run-log"))
+            .arg(clap::Arg::new("summary_json")
+                .help("Write the result of the run and of each destination to this JSON file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .required(false)
+                .long("summary-json"))
+            .arg(clap::Arg::new("report_dir")
+                .help("Write an HTML report of each run and an index page of all runs to this directory")
+                .value_parser(clap::value_parser!(PathBuf))
+                .required(false)
+                .long("report-dir"))
+            .arg(clap::Arg::new("metrics_file")
+                .help("Write Prometheus metrics after each run to this file, e.g. in the node_exporter textfile collector directory")
+                .value_parser(clap::value_parser!(PathBuf))
+                .required(false)
+                .long("metrics-file"))
+            .arg(clap::Arg::new("pushgateway")
+                .help("Push Prometheus metrics after each run to this Pushgateway url")
+                .required(false)
+                .long("pushgateway"))
+            .args(journald_arg())
+            .arg(clap::Arg::new("syslog")
+                .help("Also send the log as RFC 5424 syslog messages to unix:///dev/log or udp://host[:port]")
+                .required(false)
+                .long("syslog"))
+            .arg(clap::Arg::new("syslog_facility")
+                .help("Syslog facility, e.g. daemon, user or local0")
+                .value_parser(parse_syslog_facility)
+                .default_value("daemon")
+                .required(false)
+                .long("syslog-facility"))
+            .arg(clap::Arg::new("color")
+                .help("Colored output: auto, always or never. Auto disables colors in plain output and when NO_COLOR is set")
+                .value_parser(["auto", "always", "never"])
+                .default_value("auto")
+                .required(false)
+                .long("color"))
+            .arg(clap::Arg::new("state_dir")
+                .help("Directory for state kept between runs, including the run history database")
+                .value_parser(clap::value_parser!(PathBuf))
+                .default_value("/var/lib/dockerbackup")
+                .required(false)
+                .global(true)
+                .long("state-dir"))
+            .arg(clap::Arg::new("keep_last")
+                .help("Number of backups to keep on destinations that support retention (restic, borg and plugins)")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .required(false)
+                .long("keep-last"))
+            .arg(clap::Arg::new("size_shrink_threshold")
+                .help("Warn when a volume is this many percent smaller than its recent average, 0 disables the check")
+                .value_parser(clap::value_parser!(u64).range(0..=100))
+                .default_value("50")
+                .required(false)
+                .long("size-shrink-threshold"))
+            .arg(clap::Arg::new("size_growth_threshold")
+                .help("Warn when a volume is this many percent larger than its recent average, 0 disables the check")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("100")
+                .required(false)
+                .long("size-growth-threshold"))
+            .arg(clap::Arg::new("size_history")
+                .help("Number of past runs the recent average volume size is calculated from")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("7")
+                .required(false)
+                .long("size-history"))
+            .arg(clap::Arg::new("heartbeat_url")
+                .help("Healthchecks.io compatible ping url. Pings <url>/start when the backup starts, <url> on success and <url>/fail with a log excerpt on failure")
+                .required(false)
+                .long("heartbeat"))
+            .subcommand_negates_reqs(true)
+            .subcommand(clap::Command::new("history")
+                .about("Show past runs and the result of each destination")
+                .arg(clap::Arg::new("limit")
+                    .help("Number of runs to show")
+                    .value_parser(clap::value_parser!(usize))
+                    .default_value("20")
+                    .required(false)
+                    .short('n')
+                    .long("limit"))
+                .arg(clap::Arg::new("destination")
+                    .help("Only show runs that included this destination")
+                    .required(false)
+                    .long("destination"))
+                .arg(output_format_arg()))
+            .subcommand(clap::Command::new("status")
+                .about("Show the latest result and last success of each destination")
+                .arg(output_format_arg()))
+            .subcommand(clap::Command::new("restore")
+                .about("Restore volumes from a backup on an ssh destination")
+                .arg(clap::Arg::new("dest_path")
+                    .help("Ssh destination the backup was made to, in the same format as for backups, e.g. user@host:C:\\backup,windows")
+                    .required(true)
+                    .value_parser(parse_destination_path)
+                    .short('d')
+                    .long("destination"))
+                .arg(clap::Arg::new("backup")
+                    .help("Backup directory to restore, e.g. 2024-3-1")
+                    .required(true)
+                    .long("backup"))
+                .arg(clap::Arg::new("volume_path")
+                    .help("Path to docker volumes directory the volumes are restored to")
+                    .value_parser(clap::value_parser!(PathBuf))
+                    .default_value("/var/lib/docker/volumes")
+                    .required(false)
+                    .long("volumes"))
+                .arg(clap::Arg::new("volume")
+                    .help("Volumes to restore, every volume of the backup when not set")
+                    .required(false)
+                    .num_args(1..)
+                    .long("volume")))
             .get_matches();
 
+        if let Some((command, command_matches)) = matches.remove_subcommand() {
+            let state_dir = matches.remove_one::<PathBuf>("state_dir").unwrap();
+            let result = match command.as_str() {
+                "restore" => restore(command_matches),
+                _ => show_history(&state_dir, &command, command_matches),
+            };
+            if let Err(err) = result {
+                eprintln!("Error: {}", err);
+                exit(1);
+            }
+            exit(0);
+        }
         let excluded_containers = match matches.remove_many::<String>("excluded_containers") {
             Some(excluded_containers) => excluded_containers.collect(),
             None => Vec::new(),
@@ -121,6 +519,98 @@ impl DockerBackup {
 
         excluded_volumes.push("backingFsBlockDev".to_string());
 
+        let smtp = matches.remove_one::<String>("smtp_host").map(|host| {
+            let security =
+                SmtpSecurity::from_str(&matches.remove_one::<String>("smtp_security").unwrap())
+                    .unwrap();
+            SmtpConfig {
+                host,
+                port: matches
+                    .remove_one::<u16>("smtp_port")
+                    .unwrap_or(security.default_port()),
+                security,
+                username: matches.remove_one::<String>("smtp_user"),
+                password: matches.remove_one::<String>("smtp_password"),
+                from: matches.remove_one::<String>("smtp_from").unwrap(),
+                to: matches.remove_many::<String>("smtp_to").unwrap().collect(),
+                attach_log: matches.get_flag("smtp_attach_log"),
+                attach_report: matches.get_flag("smtp_attach_report"),
+            }
+        });
+
+        let mut notification_rules: HashMap<String, NotificationRule> = HashMap::new();
+        let notify_on: Vec<(String, Vec<NotificationEvent>)> = matches
+            .remove_many("notify_on")
+            .map(|settings| settings.collect())
+            .unwrap_or_default();
+        let min_severity: Vec<(String, Severity)> = matches
+            .remove_many("notify_min_severity")
+            .map(|settings| settings.collect())
+            .unwrap_or_default();
+        let quiet_hours: Vec<(String, QuietHours)> = matches
+            .remove_many("quiet_hours")
+            .map(|settings| settings.collect())
+            .unwrap_or_default();
+
+        //rules for "all" are applied first so specific notifiers start from them
+        for all in [true, false] {
+            let base = notification_rules.get("all").cloned().unwrap_or_default();
+            for (notifier, events) in notify_on.iter().filter(|x| (x.0 == "all") == all) {
+                notification_rules
+                    .entry(notifier.clone())
+                    .or_insert_with(|| base.clone())
+                    .on = events.clone();
+            }
+            for (notifier, severity) in min_severity.iter().filter(|x| (x.0 == "all") == all) {
+                notification_rules
+                    .entry(notifier.clone())
+                    .or_insert_with(|| base.clone())
+                    .min_severity = *severity;
+            }
+            for (notifier, window) in quiet_hours.iter().filter(|x| (x.0 == "all") == all) {
+                notification_rules
+                    .entry(notifier.clone())
+                    .or_insert_with(|| base.clone())
+                    .quiet_hours = Some(*window);
+            }
+        }
+
+        let log_format =
+            LogFormat::from_str(&matches.remove_one::<String>("log_format").unwrap()).unwrap();
+        let mut logger = Logger::new(
+            stdout(),
+            matches.get_flag("plain") || !stdout().is_terminal(),
+            ColorMode::from_str(&matches.remove_one::<String>("color").unwrap()).unwrap(),
+            log_format,
+        );
+        if let Some(path) = matches.remove_one::<PathBuf>("log_file") {
+            let file_sink = FileSink::open(LogFileConfig {
+                path,
+                format: match matches.remove_one::<String>("log_file_format") {
+                    Some(format) => LogFormat::from_str(&format).unwrap(),
+                    None => log_format,
+                },
+                max_size: matches.remove_one::<u64>("log_max_size").unwrap() * 1024 * 1024,
+                max_age: Duration::from_secs(
+                    matches.remove_one::<u64>("log_max_age").unwrap() * 24 * 60 * 60,
+                ),
+                keep: matches.remove_one::<u32>("log_keep").unwrap(),
+            })
+            .expect("Failed to open log file");
+            logger = logger.with_sink(Box::new(file_sink));
+        }
+        #[cfg(unix)]
+        if matches.get_flag("journald") {
+            let journald = JournaldSink::connect().expect("Failed to connect to journald");
+            logger = logger.with_sink(Box::new(journald));
+        }
+        if let Some(url) = matches.remove_one::<String>("syslog") {
+            let syslog =
+                SyslogSink::connect(&url, matches.remove_one::<u8>("syslog_facility").unwrap())
+                    .expect("Failed to connect to syslog");
+            logger = logger.with_sink(Box::new(syslog));
+        }
+
         DockerBackup {
             dest_paths: matches
                 .remove_many::<Arc<dyn BackupDestination>>("dest_path")
@@ -130,16 +620,105 @@ impl DockerBackup {
             volume_path: matches.remove_one::<PathBuf>("volume_path").unwrap(),
             excluded_containers,
             excluded_volumes,
-            gotify_url: matches.remove_one::<String>("gotify_url"),
+            gotify: matches
+                .remove_one::<String>("gotify_url")
+                .map(|url| GotifyConfig {
+                    url,
+                    token: matches
+                        .remove_one::<String>("gotify_token_file")
+                        .or_else(|| env::var("GOTIFY_TOKEN").ok()),
+                    priorities: match matches
+                        .remove_many::<(NotificationEvent, u8)>("gotify_priority")
+                    {
+                        Some(priorities) => priorities.collect(),
+                        None => Vec::new(),
+                    },
+                    retry: RetryPolicy {
+                        attempts: matches.remove_one::<u32>("gotify_retries").unwrap(),
+                        delay: Duration::from_secs(
+                            matches.remove_one::<u64>("gotify_retry_delay").unwrap(),
+                        ),
+                        backoff: 2,
+                        deadline: Some(Duration::from_secs(
+                            matches.remove_one::<u64>("gotify_deadline").unwrap(),
+                        )),
+                    },
+                }),
             discord_url: matches.remove_one::<String>("discord_url"),
+            slack_url: matches.remove_one::<String>("slack_url"),
+            teams_url: matches.remove_one::<String>("teams_url"),
+            keep_last: matches.remove_one::<u64>("keep_last"),
+            heartbeat: matches
+                .remove_one::<String>("heartbeat_url")
+                .map(|url| Heartbeat { url }),
+            ntfy: matches
+                .remove_one::<String>("ntfy_url")
+                .map(|url| NtfyConfig {
+                    url,
+                    token: matches.remove_one::<String>("ntfy_token"),
+                    tags: match matches.remove_many::<String>("ntfy_tags") {
+                        Some(tags) => tags.collect(),
+                        None => Vec::new(),
+                    },
+                    click: matches.remove_one::<String>("ntfy_click"),
+                }),
+            telegram: matches
+                .remove_one::<String>("telegram_token")
+                .map(|token| TelegramConfig {
+                    api_url: matches.remove_one::<String>("telegram_api").unwrap(),
+                    token,
+                    chat_id: matches.remove_one::<String>("telegram_chat").unwrap(),
+                }),
+            matrix: matches
+                .remove_one::<String>("matrix_homeserver")
+                .map(|homeserver| MatrixConfig {
+                    homeserver,
+                    token: matches.remove_one::<String>("matrix_token").unwrap(),
+                    room_id: matches.remove_one::<String>("matrix_room").unwrap(),
+                }),
+            webhooks: match matches.remove_many::<WebhookConfig>("webhook") {
+                Some(webhooks) => webhooks.collect(),
+                None => Vec::new(),
+            },
+            smtp,
+            notification_mode: NotificationMode::from_str(
+                &matches.remove_one::<String>("notification_mode").unwrap(),
+            )
+            .unwrap(),
+            notification_rules,
+            delayed_notifications: Mutex::new(Vec::new()),
+            previous_results: HashMap::new(),
+            state_dir: matches.remove_one::<PathBuf>("state_dir").unwrap(),
             receiver: None,
             sender: None,
-            logger: Arc::new(Logger::new(stdout())),
+            store_run_log: matches.get_flag("store_run_log"),
+            metrics: MetricsConfig {
+                textfile: matches.remove_one::<PathBuf>("metrics_file"),
+                pushgateway: matches.remove_one::<String>("pushgateway"),
+            },
+            summary_json: matches.remove_one::<PathBuf>("summary_json"),
+            report_dir: matches.remove_one::<PathBuf>("report_dir"),
+            report: None,
+            size_thresholds: SizeThresholds {
+                shrink: matches.remove_one::<u64>("size_shrink_threshold").unwrap(),
+                growth: matches.remove_one::<u64>("size_growth_threshold").unwrap(),
+                history: matches.remove_one::<u64>("size_history").unwrap() as usize,
+            },
+            volume_sizes: Mutex::new(None),
+            size_anomalies: Mutex::new(Vec::new()),
+            logger: Arc::new(logger),
         }
     }
-    pub fn backup(mut self) -> Result<(), BackupError> {
+    pub fn backup(mut self) -> Result<RunStatus, BackupError> {
         self.logger.clear_terminal();
-        let containers = check_running_containers()?;
+        let started_at = chrono::Local::now();
+        let timer = Instant::now();
+        self.previous_results = read_state(&self.state_dir, NOTIFICATION_STATE);
+        send_delayed_notifications(&self);
+        self.heartbeat(Ping::Start, None);
+        let containers = check_docker()
+            .and_then(|_| check_running_containers())
+            .inspect_err(|err| self.preflight_failed(started_at, err))?;
         let mut running_containers: HashSet<&str> =
             containers.trim().split('\n').collect::<HashSet<&str>>();
         running_containers.retain(|&x| !x.is_empty());
@@ -155,14 +734,12 @@ impl DockerBackup {
         let logger_ctrlc = Arc::clone(&self.logger);
         ctrlc::set_handler(move || {
             if call_count == 0 {
-                sender_clone
-                    .send(Err(BackupError::new("Backup interrupted")))
-                    .unwrap();
+                sender_clone.send(Err(BackupError::interrupted())).unwrap();
 
                 call_count += 1;
             } else {
                 logger_ctrlc.log("Forcing exit...", LogLevel::Warning);
-                exit(1);
+                exit(RunStatus::Interrupted.code());
             }
         })
         .expect("Error setting Ctrl-C handler");
@@ -170,9 +747,22 @@ impl DockerBackup {
         self.receiver = Some(receiver);
         self.sender = Some(sender);
 
+        let mut stopped_containers: Vec<String> =
+            running_containers.iter().map(|x| x.to_string()).collect();
+        stopped_containers.sort();
+        let mut restarted_containers = Vec::new();
+        let mut warnings = Vec::new();
+
         if !running_containers.is_empty() {
             self.logger.log("Stopping containers...", LogLevel::Info);
-            handle_containers(&running_containers, "stop")?;
+            handle_containers(&running_containers, "stop")
+                .inspect_err(|err| self.preflight_failed(started_at, err))?;
+            self.logger.event(
+                LogLevel::Info,
+                "containers_stopped",
+                None,
+                serde_json::json!({ "containers": stopped_containers }),
+            );
         }
 
         self.logger.hide_cursor();
@@ -181,28 +771,329 @@ impl DockerBackup {
 
         if !running_containers.is_empty() {
             self.logger.log("Starting containers...", LogLevel::Info);
-            handle_containers(&running_containers, "start")?;
+            match handle_containers(&running_containers, "start") {
+                Ok(()) => {
+                    restarted_containers = stopped_containers.clone();
+                    self.logger.event(
+                        LogLevel::Info,
+                        "containers_started",
+                        None,
+                        serde_json::json!({ "containers": restarted_containers }),
+                    );
+                }
+                Err(err) => {
+                    self.logger.log(&format!("Error: {}", err), LogLevel::Error);
+                    self.logger.event(
+                        LogLevel::Error,
+                        "containers_start_failed",
+                        None,
+                        serde_json::json!({ "containers": stopped_containers, "error": err.message }),
+                    );
+                    warnings.push(format!("Failed to restart containers: {}", err));
+                }
+            }
         }
 
-        for result in results {
+        for result in &results {
             match result {
                 Ok(success) => {
-                    success.notify(&self);
+                    self.logger.event(
+                        LogLevel::Success,
+                        "destination_finished",
+                        Some(&success.destination),
+                        serde_json::json!({
+                            "duration_seconds": success.duration.as_secs(),
+                            "size": success.size,
+                        }),
+                    );
+                    if self.notification_mode == NotificationMode::PerDestination {
+                        success.notify(&self);
+                    }
                 }
                 Err(err) => {
                     self.logger.log(&format!("Error: {}", err), LogLevel::Error);
-                    err.notify(&self);
+                    self.logger.event(
+                        LogLevel::Error,
+                        "destination_failed",
+                        err.destination.as_deref(),
+                        serde_json::json!({ "error": err.message }),
+                    );
+                    if self.notification_mode == NotificationMode::PerDestination {
+                        err.notify(&self);
+                    }
                 }
             }
         }
-        Ok(())
+
+        //size anomalies are reported, but don't make an otherwise successful run fail
+        let anomalies = self.size_anomalies.lock().unwrap().clone();
+
+        let summary = RunSummary {
+            results: &results,
+            duration: timer.elapsed(),
+            stopped_containers,
+            restarted_containers,
+            warnings,
+            anomalies,
+        };
+        let status = summary.status();
+        if let Some(dir) = &self.report_dir {
+            let report = write_report(
+                dir,
+                started_at,
+                &summary,
+                status,
+                &self
+                    .volume_sizes
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .unwrap_or_default(),
+            );
+            match report {
+                Ok(report) => self.report = Some(report),
+                Err(err) => self.logger.log(
+                    &format!("Failed to write report: {}", err),
+                    LogLevel::Warning,
+                ),
+            }
+        }
+        summary.notify(&self);
+
+        let summary_content = summary.content();
+        self.logger.event(
+            if summary_content.success {
+                LogLevel::Success
+            } else {
+                LogLevel::Error
+            },
+            "run_finished",
+            None,
+            serde_json::json!({
+                "success": summary_content.success,
+                "duration_seconds": summary.duration.as_secs(),
+                "succeeded": results.iter().filter(|result| result.is_ok()).count(),
+                "failed": results.iter().filter(|result| result.is_err()).count(),
+                "warnings": summary_content.warnings,
+                "anomalies": summary_content.anomalies,
+            }),
+        );
+        if summary_content.success {
+            self.heartbeat(Ping::Success, Some(&summary_content.message));
+        } else {
+            self.heartbeat(
+                Ping::Failure,
+                Some(&format!(
+                    "{}\n\n{}",
+                    self.logger.history(),
+                    summary_content.message
+                )),
+            );
+        }
+
+        let mut current_results: HashMap<String, bool> = self.previous_results.clone();
+        current_results.insert(String::from("run"), summary_content.success);
+        for result in &results {
+            match result {
+                Ok(success) => current_results.insert(success.destination.clone(), true),
+                Err(err) => match &err.destination {
+                    Some(destination) => current_results.insert(destination.clone(), false),
+                    None => None,
+                },
+            };
+        }
+        if let Err(err) = write_state(&self.state_dir, NOTIFICATION_STATE, &current_results) {
+            self.logger.log(
+                &format!("Failed to save notification state: {}", err),
+                LogLevel::Warning,
+            );
+        }
+        if self.metrics.is_enabled() {
+            self.export_metrics(&summary, summary_content.success);
+        }
+        self.record_history(started_at, &summary, summary_content.success);
+
+        if self.store_run_log {
+            let log = self.logger.history();
+            for dest in &self.dest_paths {
+                if let Err(err) = dest.store_run_log(&self.new_dir, &log) {
+                    self.logger.log(
+                        &format!(
+                            "Failed to store run log on {}: {}",
+                            dest.get_display_name(),
+                            err
+                        ),
+                        LogLevel::Warning,
+                    );
+                }
+            }
+        }
+
+        self.write_summary_file(status, started_at, Some(&summary), None);
+
+        save_delayed_notifications(&self);
+        Ok(status)
+    }
+
+    fn export_metrics(&self, summary: &RunSummary, success: bool) {
+        let mut state: MetricsState = read_state(&self.state_dir, METRICS_STATE);
+        let destinations: Vec<String> = self
+            .dest_paths
+            .iter()
+            .map(|dest| dest.get_display_name())
+            .collect();
+        let metrics = render_metrics(
+            summary,
+            success,
+            &destinations,
+            &self
+                .volume_sizes
+                .lock()
+                .unwrap()
+                .clone()
+                .unwrap_or_default(),
+            &mut state,
+        );
+        if let Err(err) = write_state(&self.state_dir, METRICS_STATE, &state) {
+            self.logger.log(
+                &format!("Failed to save metrics state: {}", err),
+                LogLevel::Warning,
+            );
+        }
+        if let Some(path) = &self.metrics.textfile {
+            if let Err(err) = write_textfile(path, &metrics) {
+                self.logger.log(&err.message, LogLevel::Warning);
+            }
+        }
+        if let Some(url) = &self.metrics.pushgateway {
+            if let Err(err) = push_metrics(url, &get_hostname(), &metrics) {
+                self.logger.log(&err.message, LogLevel::Warning);
+            }
+        }
+    }
+
+    fn heartbeat(&self, ping: Ping, body: Option<&str>) {
+        if let Some(heartbeat) = &self.heartbeat {
+            heartbeat.ping(&self.logger, ping, body);
+        }
+    }
+
+    fn preflight_failed(&self, started_at: chrono::DateTime<chrono::Local>, err: &BackupError) {
+        self.write_summary_file(
+            RunStatus::PreflightFailure,
+            started_at,
+            None,
+            Some(&err.message),
+        );
+        self.heartbeat(
+            Ping::Failure,
+            Some(&format!("{}\n\nError: {}", self.logger.history(), err)),
+        );
+        //no destination was started, the error is kept with the run instead
+        let summary = RunSummary {
+            results: &[],
+            duration: (chrono::Local::now() - started_at)
+                .to_std()
+                .unwrap_or_default(),
+            stopped_containers: Vec::new(),
+            restarted_containers: Vec::new(),
+            warnings: vec![err.message.clone()],
+            anomalies: Vec::new(),
+        };
+        self.record_history(started_at, &summary, false);
+        save_delayed_notifications(self);
+    }
+
+    fn record_history(
+        &self,
+        started_at: chrono::DateTime<chrono::Local>,
+        summary: &RunSummary,
+        success: bool,
+    ) {
+        if let Err(err) = RunHistory::open(&self.state_dir)
+            .and_then(|mut history| history.record(started_at, summary, success))
+        {
+            self.logger.log(
+                &format!("Failed to save run history: {}", err),
+                LogLevel::Warning,
+            );
+        }
+    }
+
+    fn write_summary_file(
+        &self,
+        status: RunStatus,
+        started_at: chrono::DateTime<chrono::Local>,
+        summary: Option<&RunSummary>,
+        error: Option<&str>,
+    ) {
+        if let Some(path) = &self.summary_json {
+            if let Err(err) = write_summary_file(path, status, started_at, summary, error) {
+                self.logger.log(&err.message, LogLevel::Warning);
+            }
+        }
+    }
+
+    fn notification_rule(&self, notifier: &str) -> NotificationRule {
+        self.notification_rules
+            .get(notifier)
+            .or_else(|| self.notification_rules.get("all"))
+            .cloned()
+            .unwrap_or_default()
+    }
+    /// Compares the measured volume sizes with earlier runs, logging every anomaly found.
+    fn find_size_anomalies(&self, sizes: &BTreeMap<String, u64>) -> Vec<String> {
+        match check_volume_sizes(
+            &self.state_dir,
+            sizes,
+            &self.excluded_volumes,
+            self.size_thresholds,
+        ) {
+            Ok(anomalies) => {
+                for anomaly in &anomalies {
+                    self.logger.log(anomaly, LogLevel::Warning);
+                    self.logger.event(
+                        LogLevel::Warning,
+                        "volume_size_anomaly",
+                        None,
+                        serde_json::json!({ "message": anomaly }),
+                    );
+                }
+                anomalies
+            }
+            Err(err) => {
+                self.logger.log(
+                    &format!("Failed to save volume size history: {}", err),
+                    LogLevel::Warning,
+                );
+                Vec::new()
+            }
+        }
     }
     fn run(&self) -> Vec<Result<BackupSuccess, BackupError>> {
         self.logger.log("Backup started...", LogLevel::Info);
+        self.logger.event(
+            LogLevel::Info,
+            "run_started",
+            None,
+            serde_json::json!({
+                "destinations": self
+                    .dest_paths
+                    .iter()
+                    .map(|dest| dest.get_display_name())
+                    .collect::<Vec<String>>(),
+                "volume_path": self.volume_path,
+            }),
+        );
         let mut results: Vec<Result<BackupSuccess, BackupError>> = Vec::new();
 
         let total_size = match get_volumes_size(&self.volume_path, &self.excluded_volumes) {
-            Ok(size) => size,
+            Ok(sizes) => {
+                let total_size = sizes.values().sum();
+                *self.size_anomalies.lock().unwrap() = self.find_size_anomalies(&sizes);
+                *self.volume_sizes.lock().unwrap() = Some(sizes);
+                total_size
+            }
             Err(err) => {
                 results.push(Err(err));
                 return results;
@@ -210,35 +1101,39 @@ impl DockerBackup {
         };
 
         self.logger.log(
-            &format!(
-                "Total size to backup: {:.2} MB",
-                total_size as f64 / (1024.0 * 1024.0)
-            ),
+            &format!("Total size to backup: {}", format_size(total_size)),
             LogLevel::Info,
         );
 
-        let mut backup_handles: Vec<(Arc<Mutex<Child>>, String)> = Vec::new();
+        let mut backup_handles: BackupHandles = Vec::new();
 
         for dest in &self.dest_paths {
             if let Err(err) = dest.check_available_space(total_size) {
-                results.push(Err(err));
+                results.push(Err(err.with_destination(dest.get_display_name())));
                 continue;
             }
 
             if let Err(err) = dest.prepare(&self.new_dir) {
-                results.push(Err(err));
+                results.push(Err(err.with_destination(dest.get_display_name())));
                 continue;
             }
 
             match dest.spawn_backup(&self.volume_path, &self.excluded_volumes, &self.new_dir) {
                 Ok(child) => {
+                    self.logger.event(
+                        LogLevel::Info,
+                        "destination_started",
+                        Some(&dest.get_display_name()),
+                        serde_json::Value::Null,
+                    );
                     backup_handles.push((
                         Arc::new(Mutex::new(child)),
                         format!("Backup to destination {}", dest.get_display_name()),
+                        Arc::clone(dest),
                     ));
                 }
                 Err(err) => {
-                    results.push(Err(err));
+                    results.push(Err(err.with_destination(dest.get_display_name())));
                 }
             }
         }
@@ -247,70 +1142,119 @@ impl DockerBackup {
             return results;
         }
 
+        //pruning now could delete the last good copies of a volume that was just wiped
+        let keep_last = match self.keep_last {
+            Some(_) if !self.size_anomalies.lock().unwrap().is_empty() => {
+                self.logger.log(
+                    "Skipping --keep-last pruning because of volume size anomalies",
+                    LogLevel::Warning,
+                );
+                None
+            }
+            keep_last => keep_last,
+        };
+
         let sender = self.sender.as_ref().unwrap();
         let mut join_handles: Vec<thread::JoinHandle<()>> = Vec::new();
 
         for (idx, handle) in backup_handles.iter().enumerate() {
             let sender_clone = sender.clone();
-            let handle = handle.clone();
+            let (child, description, dest) = handle.clone();
             let logger_clone = Arc::clone(&self.logger);
             let join_handle = thread::spawn(move || {
                 let timer = Instant::now();
-                let stderr = handle.0.lock().unwrap().stderr.take();
-                let mut stderr_reader = stderr.map(BufReader::new);
-                let mut buffer = Vec::new();
+                let progress: Arc<Mutex<Progress>> = Arc::new(Mutex::new(Progress::default()));
+                let stdout = child.lock().unwrap().stdout.take();
+                let stderr = child.lock().unwrap().stderr.take();
+                let stdout_watcher =
+                    stdout.map(|out| watch_output(out, Arc::clone(&dest), Arc::clone(&progress)));
+                let stderr_watcher =
+                    stderr.map(|err| watch_output(err, Arc::clone(&dest), Arc::clone(&progress)));
                 loop {
-                    if let Ok(status) = handle.0.lock().unwrap().try_wait() {
+                    let status = child.lock().unwrap().try_wait();
+                    if let Ok(status) = status {
                         if let Some(status) = status {
+                            let mut messages = Vec::new();
+                            for watcher in [stdout_watcher, stderr_watcher].into_iter().flatten() {
+                                if let Ok(lines) = watcher.join() {
+                                    messages.extend(lines);
+                                }
+                            }
                             if status.success() {
-                                let msg = get_elapsed_time(
-                                    timer,
-                                    format!("{} completed successfully in", handle.1).as_str(),
-                                );
-                                logger_clone.log_elapsed_time(idx, &msg, Color::Green);
-                                sender_clone.send(Ok(msg)).unwrap();
-                                return;
-                            } else if let Some(reader) = stderr_reader.as_mut() {
-                                match reader.read_to_end(&mut buffer) {
-                                    Ok(_) => {
-                                        let stderr_output = String::from_utf8_lossy(&buffer);
-                                        sender_clone
-                                            .send(Err(BackupError::new(&stderr_output)))
-                                            .unwrap();
-                                        return;
-                                    }
-                                    Err(e) => {
-                                        logger_clone.log(
-                                            &format!("Failed to read stderr: {}", e),
-                                            LogLevel::Error,
-                                        );
+                                if let Some(keep_last) = keep_last {
+                                    if let Err(err) = dest.prune(keep_last) {
                                         sender_clone
-                                            .send(Err(BackupError::new(&format!(
-                                                "{} backup error",
-                                                handle.1
-                                            ))))
+                                            .send(
+                                                Err(err.with_destination(dest.get_display_name())),
+                                            )
                                             .unwrap();
                                         return;
                                     }
                                 }
+                                let msg = get_elapsed_time(
+                                    timer,
+                                    format!("{} completed successfully in", description).as_str(),
+                                );
+                                logger_clone.log_elapsed_time(idx, &msg, LogLevel::Success);
+                                let transferred = progress
+                                    .lock()
+                                    .unwrap()
+                                    .transferred
+                                    .or_else(|| dest.transferred_bytes());
+                                sender_clone
+                                    .send(Ok(BackupSuccess::new(
+                                        &msg,
+                                        dest.get_display_name(),
+                                        timer.elapsed(),
+                                        total_size,
+                                    )
+                                    .with_transferred(transferred)))
+                                    .unwrap();
+                            } else if !messages.is_empty() {
+                                sender_clone
+                                    .send(Err(BackupError::new(&messages.join("\n"))
+                                        .with_destination(dest.get_display_name())))
+                                    .unwrap();
                             } else {
                                 sender_clone
                                     .send(Err(BackupError::new(&format!(
                                         "{} backup error",
-                                        handle.1
-                                    ))))
+                                        description
+                                    ))
+                                    .with_destination(dest.get_display_name())))
                                     .unwrap();
-                                return;
                             }
+                            return;
                         } else {
-                            logger_clone.log_elapsed_time(
+                            let mut msg = get_elapsed_time(
+                                timer,
+                                format!("\r{} running time", description).as_str(),
+                            );
+                            let progress = progress.lock().unwrap();
+                            let transferred =
+                                progress.transferred.or_else(|| dest.transferred_bytes());
+                            if let Some(transferred) = transferred {
+                                msg = format!(
+                                    "{} {}",
+                                    msg,
+                                    format_progress(transferred, total_size, timer.elapsed())
+                                );
+                            }
+                            if let Some(message) = &progress.message {
+                                msg = format!("{} ({})", msg, message);
+                            }
+                            logger_clone.progress(
                                 idx,
-                                &get_elapsed_time(
-                                    timer,
-                                    format!("\r{} running time", handle.1).as_str(),
-                                ),
-                                Color::Cyan,
+                                &dest.get_display_name(),
+                                serde_json::json!({
+                                    "elapsed_seconds": timer.elapsed().as_secs(),
+                                    "transferred": transferred,
+                                    "total": total_size,
+                                    "status": progress.message,
+                                }),
                             );
+                            drop(progress);
+                            logger_clone.log_elapsed_time(idx, &msg, LogLevel::Info);
                             thread::sleep(std::time::Duration::from_secs(1));
                         }
                     }
@@ -324,12 +1268,12 @@ impl DockerBackup {
                 Ok(message) => {
                     match message {
                         Ok(result) => {
-                            results.push(Ok(BackupSuccess::new(&result)));
+                            results.push(Ok(result));
                         }
                         Err(err) => {
-                            if err.message == "Backup interrupted" {
+                            if err.interrupted {
                                 for handle in backup_handles {
-                                    if let Err(err) = handle.0.lock().unwrap().kill() {
+                                    if let Err(err) = handle.2.kill(&mut handle.0.lock().unwrap()) {
                                         self.logger.log(
                                             &format!("Error killing process: {:?}", err),
                                             LogLevel::Error,
@@ -353,7 +1297,7 @@ impl DockerBackup {
                                     LogLevel::Warning,
                                 );
 
-                                results.push(Err(BackupError::new("Backup interrupted")));
+                                results.push(Err(BackupError::interrupted()));
                                 return results;
                             }
                             results.push(Err(err));
@@ -382,3 +1326,74 @@ impl DockerBackup {
         }
     }
 }
+
+/// The journal only exists on unix, elsewhere the flag is rejected as an unknown argument.
+fn journald_arg() -> Option<clap::Arg> {
+    cfg!(unix).then(|| {
+        clap::Arg::new("journald")
+            .help("Also send the log to the systemd journal with DOCKERBACKUP_* fields")
+            .required(false)
+            .action(ArgAction::SetTrue)
+            .long("journald")
+    })
+}
+
+fn output_format_arg() -> clap::Arg {
+    clap::Arg::new("format")
+        .help("Output format")
+        .value_parser(["table", "json"])
+        .default_value("table")
+        .required(false)
+        .long("format")
+}
+
+fn show_history(
+    state_dir: &Path,
+    command: &str,
+    mut matches: clap::ArgMatches,
+) -> Result<(), BackupError> {
+    let history = RunHistory::open(state_dir)?;
+    let json = matches.remove_one::<String>("format").unwrap() == "json";
+    let output = match command {
+        "history" => {
+            let runs = history.runs(
+                matches.remove_one::<usize>("limit").unwrap(),
+                matches.remove_one::<String>("destination").as_deref(),
+            )?;
+            if json {
+                serde_json::to_string_pretty(&runs).unwrap()
+            } else {
+                runs_table(&runs)
+            }
+        }
+        _ => {
+            let statuses = history.status()?;
+            if json {
+                serde_json::to_string_pretty(&statuses).unwrap()
+            } else {
+                status_table(&statuses)
+            }
+        }
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+fn restore(mut matches: clap::ArgMatches) -> Result<(), BackupError> {
+    let dest = matches
+        .remove_one::<Arc<dyn BackupDestination>>("dest_path")
+        .unwrap();
+    let volumes: Vec<String> = match matches.remove_many::<String>("volume") {
+        Some(volumes) => volumes.collect(),
+        None => Vec::new(),
+    };
+    let restored = dest.restore(
+        &matches.remove_one::<String>("backup").unwrap(),
+        &volumes,
+        &matches.remove_one::<PathBuf>("volume_path").unwrap(),
+    )?;
+    for volume in restored {
+        println!("Restored volume {}", volume);
+    }
+    Ok(())
+}
@@ -1,118 +1,1430 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::{thread, time};
 
+use serde::{Deserialize, Serialize};
+
 use crate::backup::logger::{LogLevel, Logger};
+use crate::backup::smtp::{build_message, send_mail, Attachment, SmtpConfig};
+use crate::backup::state::{read_state, write_state};
+use crate::backup::utils::{format_duration, format_size, get_hostname};
+use crate::backup::DockerBackup;
+
+const DELAYED_NOTIFICATIONS_STATE: &str = "delayed_notifications.json";
+
+const DEFAULT_WEBHOOK_TEMPLATE: &str = r#"{
+  "status": {{ status | tojson }},
+  "message": {{ message | tojson }},
+  "destination": {{ destination | tojson }},
+  "duration": {{ duration | tojson }},
+  "size": {{ size | tojson }},
+  "errors": {{ errors | tojson }},
+  "destinations": {{ destinations | tojson }},
+  "stopped_containers": {{ stopped_containers | tojson }},
+  "restarted_containers": {{ restarted_containers | tojson }},
+  "warnings": {{ warnings | tojson }},
+  "anomalies": {{ anomalies | tojson }},
+  "hostname": {{ hostname | tojson }}
+}"#;
 
 pub trait Notification {
     fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>>;
 }
 
 pub struct Gotify<'a> {
-    pub message: Option<String>,
-    pub url: &'a String,
-    pub success: bool,
+    pub content: &'a NotificationContent,
+    pub config: &'a GotifyConfig,
     pub logger: &'a Logger,
 }
 
 pub struct Discord<'a> {
-    pub message: Option<String>,
+    pub content: &'a NotificationContent,
     pub url: &'a String,
+    pub logger: &'a Logger,
+}
+
+pub struct Ntfy<'a> {
+    pub content: &'a NotificationContent,
+    pub config: &'a NtfyConfig,
+    pub logger: &'a Logger,
+}
+
+pub struct Slack<'a> {
+    pub content: &'a NotificationContent,
+    pub url: &'a String,
+    pub logger: &'a Logger,
+}
+
+pub struct Teams<'a> {
+    pub content: &'a NotificationContent,
+    pub url: &'a String,
+    pub logger: &'a Logger,
+}
+
+pub struct Telegram<'a> {
+    pub content: &'a NotificationContent,
+    pub config: &'a TelegramConfig,
+    pub logger: &'a Logger,
+}
+
+pub struct Matrix<'a> {
+    pub content: &'a NotificationContent,
+    pub config: &'a MatrixConfig,
+    pub logger: &'a Logger,
+}
+
+pub struct Email<'a> {
+    pub config: &'a SmtpConfig,
+    pub content: &'a NotificationContent,
+    pub log: Option<String>,
+    pub report: Option<String>,
+}
+
+pub struct Webhook<'a> {
+    pub content: &'a NotificationContent,
+    pub config: &'a WebhookConfig,
+    pub logger: &'a Logger,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct NotificationContent {
     pub success: bool,
+    pub message: String,
+    pub destination: Option<String>,
+    pub duration: Option<time::Duration>,
+    pub size: Option<u64>,
+    pub errors: Vec<String>,
+    pub outcomes: Vec<DestinationOutcome>,
+    pub stopped_containers: Vec<String>,
+    pub restarted_containers: Vec<String>,
+    pub warnings: Vec<String>,
+    //missing from notifications delayed by older versions
+    #[serde(default)]
+    pub anomalies: Vec<String>,
+    pub recovery: bool,
+}
+
+impl NotificationContent {
+    pub fn event(&self) -> NotificationEvent {
+        if !self.errors.is_empty() {
+            NotificationEvent::Failure
+        } else if !self.success || !self.anomalies.is_empty() {
+            NotificationEvent::Warning
+        } else if self.recovery {
+            NotificationEvent::Recovery
+        } else {
+            NotificationEvent::Success
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self.event() {
+            NotificationEvent::Failure => Severity::Critical,
+            NotificationEvent::Warning => Severity::Warning,
+            NotificationEvent::Success | NotificationEvent::Recovery => Severity::Info,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn from_str(severity: &str) -> Result<Self, String> {
+        match severity.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("Unsupported severity {}", severity)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NotificationEvent {
+    Success,
+    Warning,
+    Failure,
+    Recovery,
+}
+
+impl NotificationEvent {
+    pub fn from_str(event: &str) -> Result<Self, String> {
+        match event.to_lowercase().as_str() {
+            "success" => Ok(NotificationEvent::Success),
+            "warning" => Ok(NotificationEvent::Warning),
+            "failure" => Ok(NotificationEvent::Failure),
+            "recovery" => Ok(NotificationEvent::Recovery),
+            _ => Err(format!("Unsupported notification event {}", event)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QuietHours {
+    pub start: chrono::NaiveTime,
+    pub end: chrono::NaiveTime,
+}
+
+impl QuietHours {
+    fn contains(&self, time: chrono::NaiveTime) -> bool {
+        if self.start <= self.end {
+            time >= self.start && time < self.end
+        } else {
+            //window spans midnight, e.g. 22:00-07:00
+            time >= self.start || time < self.end
+        }
+    }
+
+    fn next_end(&self, now: chrono::DateTime<chrono::Local>) -> chrono::DateTime<chrono::Local> {
+        let mut end = now.date_naive().and_time(self.end);
+        if end <= now.naive_local() {
+            end += chrono::Duration::days(1);
+        }
+        end.and_local_timezone(chrono::Local)
+            .earliest()
+            .unwrap_or(now)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NotificationRule {
+    pub on: Vec<NotificationEvent>,
+    pub min_severity: Severity,
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for NotificationRule {
+    fn default() -> Self {
+        NotificationRule {
+            on: vec![
+                NotificationEvent::Success,
+                NotificationEvent::Warning,
+                NotificationEvent::Failure,
+                NotificationEvent::Recovery,
+            ],
+            min_severity: Severity::Info,
+            quiet_hours: None,
+        }
+    }
+}
+
+pub enum RuleDecision {
+    Send,
+    Skip,
+    Delay(chrono::DateTime<chrono::Local>),
+}
+
+impl NotificationRule {
+    pub fn decide(
+        &self,
+        content: &NotificationContent,
+        now: chrono::DateTime<chrono::Local>,
+    ) -> RuleDecision {
+        let event = content.event();
+        //a recovery is also a success, so rules listening for successes get it too
+        let matches = self.on.contains(&event)
+            || (event == NotificationEvent::Recovery
+                && self.on.contains(&NotificationEvent::Success));
+        if !matches || content.severity() < self.min_severity {
+            return RuleDecision::Skip;
+        }
+
+        match self.quiet_hours {
+            Some(quiet_hours)
+                if content.severity() < Severity::Critical && quiet_hours.contains(now.time()) =>
+            {
+                RuleDecision::Delay(quiet_hours.next_end(now))
+            }
+            _ => RuleDecision::Send,
+        }
+    }
+}
+
+/// A notification held back by quiet hours, saved in the state directory until a later run sends it.
+#[derive(Serialize, Deserialize)]
+pub struct DelayedNotification {
+    pub until: chrono::DateTime<chrono::Local>,
+    pub notifier: usize,
+    pub name: String,
+    pub content: NotificationContent,
+    //missing from notifications delayed by older versions
+    #[serde(default)]
+    pub attachments: EmailAttachments,
+}
+
+/// Run log and HTML report attached to email notifications. Saved with a delayed email, so it
+/// is sent with the run it describes rather than with the run that finally sends it.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct EmailAttachments {
+    pub log: Option<String>,
+    pub report: Option<String>,
+}
+
+impl EmailAttachments {
+    fn of_run(config: &DockerBackup) -> Self {
+        match &config.smtp {
+            Some(smtp) => EmailAttachments {
+                log: smtp.attach_log.then(|| config.logger.history()),
+                report: smtp.attach_report.then(|| config.report.clone()).flatten(),
+            },
+            None => EmailAttachments::default(),
+        }
+    }
+}
+
+pub const NOTIFIERS: [&str; 10] = [
+    "all", "gotify", "discord", "slack", "teams", "telegram", "matrix", "ntfy", "webhook", "email",
+];
+
+/// Result of a single destination in a run summary, `detail` holds the duration or the error.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DestinationOutcome {
+    pub destination: String,
+    pub success: bool,
+    pub detail: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NotificationMode {
+    Summary,
+    PerDestination,
+}
+
+impl NotificationMode {
+    pub fn from_str(mode: &str) -> Result<Self, String> {
+        match mode.to_lowercase().as_str() {
+            "summary" => Ok(NotificationMode::Summary),
+            "per-destination" => Ok(NotificationMode::PerDestination),
+            _ => Err(String::from("Unsupported notification mode")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub template: Option<String>,
+    pub template_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GotifyConfig {
+    pub url: String,
+    pub token: Option<String>,
+    pub priorities: Vec<(NotificationEvent, u8)>,
+    pub retry: RetryPolicy,
+}
+
+impl GotifyConfig {
+    fn priority(&self, event: NotificationEvent) -> u8 {
+        //the last --gotify-priority for an event wins
+        self.priorities
+            .iter()
+            .rev()
+            .find(|(priority_event, _)| *priority_event == event)
+            .map(|(_, priority)| *priority)
+            .unwrap_or(match event {
+                NotificationEvent::Success => 2,
+                NotificationEvent::Recovery => 4,
+                NotificationEvent::Warning => 5,
+                NotificationEvent::Failure => 8,
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NtfyConfig {
+    pub url: String,
+    pub token: Option<String>,
+    pub tags: Vec<String>,
+    pub click: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TelegramConfig {
+    /// Bot API server, `https://api.telegram.org` unless a local server is used.
+    pub api_url: String,
+    pub token: String,
+    pub chat_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatrixConfig {
+    pub homeserver: String,
+    pub token: String,
+    pub room_id: String,
+}
+
+fn default_webhook_method() -> String {
+    String::from("POST")
 }
 
 impl<'a> Notification for Gotify<'a> {
     fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let mut map: HashMap<&str, &str> = HashMap::new();
+        let mut message = String::new();
+        if self.content.outcomes.is_empty() && self.content.errors.is_empty() {
+            message.push_str(&format!("{}\n\n", self.content.message.trim()));
+        }
+        for (name, value) in content_fields(self.content) {
+            message.push_str(&format!("- **{}:** {}\n", name, value.replace('\n', " ")));
+        }
+        if !self.content.errors.is_empty() {
+            message.push_str(&format!(
+                "\n```\n{}\n```\n",
+                excerpt(&self.content.errors.join("\n"), 4000).replace("```", "'''")
+            ));
+        }
 
-        let message = if let Some(msg) = &self.message {
-            msg
-        } else if self.success {
-            "Backup successful"
-        } else {
-            "Backup failed"
+        let payload = GotifyMessage {
+            title: status_text(self.content),
+            message: &message,
+            priority: self.config.priority(self.content.event()),
+            extras: GotifyExtras {
+                display: GotifyDisplay {
+                    content_type: "text/markdown",
+                },
+            },
         };
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
 
-        map.insert("title", "Backup result");
-        map.insert("message", message);
-        let client = reqwest::blocking::Client::new();
-
-        for attempt in 0..10 {
-            self.logger.log(
-                &format!("Sending request to Gotify.Attempt {}", attempt),
-                LogLevel::Info,
-            );
-            let _req = client
-                .post(self.url)
+        send_with_retry(self.logger, "Gotify", &self.config.retry, || {
+            let mut request = client
+                .post(&self.config.url)
                 .header("Accept", "application/json")
-                .header("Content-Type", "application/json")
-                .json(&map)
-                .send();
-            if let Ok(response) = _req {
-                if response.status().is_success() {
-                    return Ok(());
-                }
+                .json(&payload);
+            if let Some(token) = &self.config.token {
+                request = request.header("X-Gotify-Key", token);
             }
-            thread::sleep(time::Duration::from_secs(10));
+            request.send()
+        })
+    }
+}
+
+impl<'a> Notification for Ntfy<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut message = String::new();
+        if self.content.outcomes.is_empty() && self.content.errors.is_empty() {
+            message.push_str(&format!("{}\n\n", self.content.message.trim()));
+        }
+        for (name, value) in content_fields(self.content) {
+            message.push_str(&format!("{}: {}\n", name, value.replace('\n', " ")));
         }
-        Err(Box::from(
-            "Error sending request to gotify after 10 attempts",
-        ))
+        if !self.content.errors.is_empty() {
+            message.push_str(&format!(
+                "\n{}\n",
+                excerpt(&self.content.errors.join("\n"), 3000)
+            ));
+        }
+
+        let event = self.content.event();
+        let mut tags = vec![match event {
+            NotificationEvent::Success | NotificationEvent::Recovery => "white_check_mark",
+            NotificationEvent::Warning => "warning",
+            NotificationEvent::Failure => "rotating_light",
+        }];
+        tags.extend(self.config.tags.iter().map(|tag| tag.as_str()));
+        let priority = match event {
+            NotificationEvent::Failure => "high",
+            _ => "default",
+        };
+
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "ntfy", &RetryPolicy::BACKOFF, || {
+            let mut request = client
+                .post(&self.config.url)
+                .header("Title", status_text(self.content))
+                .header("Priority", priority)
+                .header("Tags", tags.join(","));
+            if let Some(token) = &self.config.token {
+                request = request.bearer_auth(token);
+            }
+            if let Some(click) = &self.config.click {
+                request = request.header("Click", click);
+            }
+            request.body(message.trim_end().to_string()).send()
+        })
     }
 }
 
-impl<'a> Notification for Discord<'a> {
+impl<'a> Notification for Slack<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = SlackPayload::new(self.content);
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "Slack", &RetryPolicy::BACKOFF, || {
+            client.post(self.url).json(&payload).send()
+        })
+    }
+}
+
+impl<'a> Notification for Teams<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = TeamsPayload::new(self.content);
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "Teams", &RetryPolicy::BACKOFF, || {
+            client.post(self.url).json(&payload).send()
+        })
+    }
+}
+
+impl<'a> Notification for Telegram<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = serde_json::json!({
+            "chat_id": self.config.chat_id,
+            "text": html_summary(self.content, TELEGRAM_TEXT_LIMIT, "\n"),
+            "parse_mode": "HTML",
+            "disable_web_page_preview": true
+        });
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            self.config.api_url.trim_end_matches('/'),
+            self.config.token
+        );
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "Telegram", &RetryPolicy::BACKOFF, || {
+            client.post(&url).json(&payload).send()
+        })
+    }
+}
+
+impl<'a> Notification for Matrix<'a> {
     fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let status_field = format!(
-            r#"{{
-            "name": "Status",
-            "value": "{}"
-        }}"#,
-            if self.success { "Success" } else { "Failed" }
+        let mut url = reqwest::Url::parse(&self.config.homeserver)?;
+        //the transaction id makes retried requests idempotent
+        let transaction_id = format!(
+            "dockerbackup-{}",
+            chrono::Local::now()
+                .timestamp_nanos_opt()
+                .unwrap_or_default()
+        );
+        url.path_segments_mut()
+            .map_err(|_| "Invalid matrix homeserver url")?
+            .pop_if_empty()
+            .extend([
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.config.room_id,
+                "send",
+                "m.room.message",
+                &transaction_id,
+            ]);
+
+        let mut plain = format!("{}\n", status_text(self.content));
+        for (name, value) in content_fields(self.content) {
+            plain.push_str(&format!("{}: {}\n", name, value));
+        }
+        plain.push_str(&self.content.errors.join("\n"));
+
+        let payload = serde_json::json!({
+            "msgtype": "m.text",
+            "body": plain.trim_end(),
+            "format": "org.matrix.custom.html",
+            "formatted_body": html_summary(self.content, MATRIX_HTML_LIMIT, "<br>")
+        });
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "Matrix", &RetryPolicy::BACKOFF, || {
+            client
+                .put(url.clone())
+                .bearer_auth(&self.config.token)
+                .json(&payload)
+                .send()
+        })
+    }
+}
+
+//limits on the html sent to chat services, telegram rejects messages above 4096 characters
+const TELEGRAM_TEXT_LIMIT: usize = 4096;
+const MATRIX_HTML_LIMIT: usize = 16000;
+const HTML_FIELD_LIMIT: usize = 1000;
+
+/// Telegram only accepts newlines between lines while Matrix clients expect `<br>`.
+/// The result stays within `max_chars`, fields that don't fit are left out whole so
+/// every tag is closed.
+fn html_summary(content: &NotificationContent, max_chars: usize, line_break: &str) -> String {
+    let mut html = format!(
+        "<b>{} {}</b>",
+        match content.event() {
+            NotificationEvent::Success | NotificationEvent::Recovery => "✅",
+            NotificationEvent::Warning => "⚠️",
+            NotificationEvent::Failure => "❌",
+        },
+        status_text(content)
+    );
+    let errors = content.errors.join("\n");
+    //the error output gets up to half of the message, the fields share the rest
+    let error_reserve = if errors.is_empty() {
+        0
+    } else {
+        (escape_html(&errors).chars().count() + line_break.len() + "<pre></pre>".len())
+            .min(max_chars / 2)
+    };
+    let mut length = html.chars().count();
+    for (name, value) in content_fields(content) {
+        let line = format!(
+            "{}<b>{}:</b> {}",
+            line_break,
+            escape_html(&name),
+            escaped_excerpt(&value, HTML_FIELD_LIMIT)
         );
-        let error_message_field = format!(
-            r#",{{
-            "name": "Message",
-            "value": "{}"
-        }}"#,
-            if let Some(msg) = &self.message {
-                msg
+        let line_length = line.chars().count();
+        if length + line_length + error_reserve <= max_chars {
+            html.push_str(&line);
+            length += line_length;
+        }
+    }
+    let budget = max_chars.saturating_sub(length + line_break.len() + "<pre></pre>".len());
+    if !errors.is_empty() && budget > 0 {
+        html.push_str(&format!(
+            "{}<pre>{}</pre>",
+            line_break,
+            escaped_excerpt(&errors, budget)
+        ));
+    }
+    html
+}
+
+/// Escapes `text` for html and shortens it so the escaped text has at most `max_chars` characters.
+fn escaped_excerpt(text: &str, max_chars: usize) -> String {
+    let mut limit = max_chars;
+    loop {
+        let escaped = escape_html(&excerpt(text, limit));
+        let length = escaped.chars().count();
+        if length <= max_chars || limit <= 1 {
+            return escaped;
+        }
+        limit = limit.saturating_sub(length - max_chars).max(1);
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+//runs where every destination succeeded but e.g. containers didn't start are not failures
+fn status_text(content: &NotificationContent) -> &'static str {
+    match content.event() {
+        NotificationEvent::Success | NotificationEvent::Recovery => "Docker backup succeeded",
+        NotificationEvent::Warning => "Docker backup finished with warnings",
+        NotificationEvent::Failure => "Docker backup failed",
+    }
+}
+
+fn content_fields(content: &NotificationContent) -> Vec<(String, String)> {
+    let mut fields = vec![(
+        String::from("Status"),
+        String::from(match content.event() {
+            NotificationEvent::Success | NotificationEvent::Recovery => "Success",
+            NotificationEvent::Warning => "Warning",
+            NotificationEvent::Failure => "Failed",
+        }),
+    )];
+    if let Some(destination) = &content.destination {
+        fields.push((String::from("Destination"), destination.clone()));
+    }
+    for outcome in &content.outcomes {
+        fields.push((
+            format!(
+                "{} {}",
+                if outcome.success { "✅" } else { "❌" },
+                outcome.destination
+            ),
+            if outcome.success {
+                outcome.detail.clone()
             } else {
-                "No message"
+                String::from("Failed")
+            },
+        ));
+    }
+    if let Some(duration) = content.duration {
+        fields.push((String::from("Duration"), format_duration(duration)));
+    }
+    if let Some(size) = content.size {
+        fields.push((String::from("Size"), format_size(size)));
+    }
+    if !content.stopped_containers.is_empty() {
+        fields.push((
+            String::from("Stopped containers"),
+            content.stopped_containers.join(", "),
+        ));
+    }
+    if !content.restarted_containers.is_empty() {
+        fields.push((
+            String::from("Restarted containers"),
+            content.restarted_containers.join(", "),
+        ));
+    }
+    if !content.warnings.is_empty() {
+        fields.push((String::from("Warnings"), content.warnings.join("\n")));
+    }
+    if !content.anomalies.is_empty() {
+        fields.push((String::from("Size anomalies"), content.anomalies.join("\n")));
+    }
+    fields.push((String::from("Host"), get_hostname()));
+    fields
+}
+
+/// Shortens `text` to at most `max_chars` characters, keeping the beginning.
+fn excerpt(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut excerpt: String = text.chars().take(max_chars - 1).collect();
+    excerpt.push('…');
+    excerpt
+}
+
+impl<'a> Notification for Email<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let hostname = get_hostname();
+        let failed = self
+            .content
+            .outcomes
+            .iter()
+            .filter(|outcome| !outcome.success)
+            .count();
+        let subject = match self.content.event() {
+            NotificationEvent::Success | NotificationEvent::Recovery => {
+                format!("Docker backup succeeded on {}", hostname)
             }
+            _ if failed > 0 => format!(
+                "Docker backup failed on {} ({} of {} destinations)",
+                hostname,
+                failed,
+                self.content.outcomes.len()
+            ),
+            _ => format!("Docker backup finished with warnings on {}", hostname),
+        };
+
+        let mut body = format!(
+            "Docker backup results for {}:\n\n{}\n",
+            hostname, self.content.message
         );
-        let json = format!(
-            r#"
-        {{
-            "embeds": [
-                {{
-                    "title": "Docker backup result",
-                    "fields": [
-                        {}
-                        {}
-                    ]
-                }}
-            ]
-        }}
-    "#,
-            status_field, error_message_field
+        if let Some(duration) = self.content.duration {
+            body.push_str(&format!("Run time: {}\n", format_duration(duration)));
+        }
+
+        let mut attachments = Vec::new();
+        if let Some(log) = &self.log {
+            attachments.push(Attachment {
+                file_name: "dockerbackup.log",
+                content_type: "text/plain",
+                content: log,
+            });
+        }
+        if let Some(report) = &self.report {
+            attachments.push(Attachment {
+                file_name: "dockerbackup-report.html",
+                content_type: "text/html",
+                content: report,
+            });
+        }
+        send_mail(
+            self.config,
+            &build_message(self.config, &subject, &body, &attachments),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub delay: time::Duration,
+    pub backoff: u32,
+    /// Gives up once the next attempt would start after this much time.
+    pub deadline: Option<time::Duration>,
+}
+
+impl RetryPolicy {
+    const BACKOFF: RetryPolicy = RetryPolicy {
+        attempts: 5,
+        delay: time::Duration::from_secs(2),
+        backoff: 2,
+        deadline: None,
+    };
+}
+
+fn send_with_retry<F>(
+    logger: &Logger,
+    service: &str,
+    policy: &RetryPolicy,
+    send: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Fn() -> reqwest::Result<reqwest::blocking::Response>,
+{
+    let started = time::Instant::now();
+    let mut delay = policy.delay;
+    for attempt in 0..policy.attempts {
+        logger.log(
+            &format!("Sending request to {}.Attempt {}", service, attempt),
+            LogLevel::Info,
         );
-        let client = reqwest::blocking::Client::new();
-        let _req = client
-            .post(self.url)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .body(json)
-            .send();
-        if _req?.status().is_success() {
-            Ok(())
+        if let Ok(response) = send() {
+            if response.status().is_success() {
+                return Ok(());
+            }
+        }
+        if attempt + 1 < policy.attempts {
+            if let Some(deadline) = policy.deadline {
+                if started.elapsed() + delay > deadline {
+                    return Err(Box::from(format!(
+                        "Error sending request to {}, retry deadline of {}s exceeded",
+                        service,
+                        deadline.as_secs()
+                    )));
+                }
+            }
+            thread::sleep(delay);
+            delay *= policy.backoff;
+        }
+    }
+    Err(Box::from(format!(
+        "Error sending request to {} after {} attempts",
+        service, policy.attempts
+    )))
+}
+
+#[derive(Serialize)]
+struct GotifyMessage<'a> {
+    title: &'a str,
+    message: &'a str,
+    priority: u8,
+    extras: GotifyExtras,
+}
+
+#[derive(Serialize)]
+struct GotifyExtras {
+    #[serde(rename = "client::display")]
+    display: GotifyDisplay,
+}
+
+#[derive(Serialize)]
+struct GotifyDisplay {
+    #[serde(rename = "contentType")]
+    content_type: &'static str,
+}
+
+//limits enforced by the slack api for section fields and texts
+const SLACK_MAX_FIELDS: usize = 10;
+const SLACK_FIELD_LIMIT: usize = 2000;
+const SLACK_TEXT_LIMIT: usize = 3000;
+
+#[derive(Serialize)]
+struct SlackPayload {
+    text: &'static str,
+    attachments: Vec<SlackAttachment>,
+}
+
+#[derive(Serialize)]
+struct SlackAttachment {
+    color: &'static str,
+    blocks: Vec<SlackBlock>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SlackBlock {
+    Header {
+        text: SlackText,
+    },
+    Section {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<SlackText>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<SlackText>,
+    },
+}
+
+#[derive(Serialize)]
+struct SlackText {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+}
+
+impl SlackPayload {
+    fn new(content: &NotificationContent) -> Self {
+        let fields = content_fields(content)
+            .into_iter()
+            .take(SLACK_MAX_FIELDS)
+            .map(|(name, value)| SlackText {
+                kind: "mrkdwn",
+                text: excerpt(&format!("*{}*\n{}", name, value), SLACK_FIELD_LIMIT),
+            })
+            .collect();
+
+        let mut blocks = vec![
+            SlackBlock::Header {
+                text: SlackText {
+                    kind: "plain_text",
+                    text: String::from("Docker backup result"),
+                },
+            },
+            SlackBlock::Section { text: None, fields },
+        ];
+        if !content.errors.is_empty() {
+            blocks.push(SlackBlock::Section {
+                text: Some(SlackText {
+                    kind: "mrkdwn",
+                    text: format!(
+                        "```{}```",
+                        excerpt(&content.errors.join("\n"), SLACK_TEXT_LIMIT - 6)
+                    ),
+                }),
+                fields: Vec::new(),
+            });
+        }
+
+        SlackPayload {
+            text: status_text(content),
+            attachments: vec![SlackAttachment {
+                color: match content.event() {
+                    NotificationEvent::Success | NotificationEvent::Recovery => "#2eb886",
+                    NotificationEvent::Warning => "#ecb22e",
+                    NotificationEvent::Failure => "#e01e5a",
+                },
+                blocks,
+            }],
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TeamsPayload {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    attachments: Vec<TeamsAttachment>,
+}
+
+#[derive(Serialize)]
+struct TeamsAttachment {
+    #[serde(rename = "contentType")]
+    content_type: &'static str,
+    content: AdaptiveCard,
+}
+
+#[derive(Serialize)]
+struct AdaptiveCard {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    version: &'static str,
+    body: Vec<CardElement>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type")]
+enum CardElement {
+    TextBlock {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        weight: Option<&'static str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<&'static str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        color: Option<&'static str>,
+        #[serde(rename = "fontType", skip_serializing_if = "Option::is_none")]
+        font_type: Option<&'static str>,
+        wrap: bool,
+    },
+    FactSet {
+        facts: Vec<TeamsFact>,
+    },
+}
+
+#[derive(Serialize)]
+struct TeamsFact {
+    title: String,
+    value: String,
+}
+
+impl TeamsPayload {
+    fn new(content: &NotificationContent) -> Self {
+        let facts = content_fields(content)
+            .into_iter()
+            .map(|(title, value)| TeamsFact { title, value })
+            .collect();
+
+        let mut body = vec![
+            CardElement::TextBlock {
+                text: String::from("Docker backup result"),
+                weight: Some("Bolder"),
+                size: Some("Medium"),
+                color: None,
+                font_type: None,
+                wrap: false,
+            },
+            CardElement::TextBlock {
+                text: status_text(content).to_string(),
+                weight: Some("Bolder"),
+                size: None,
+                color: Some(match content.event() {
+                    NotificationEvent::Success | NotificationEvent::Recovery => "Good",
+                    NotificationEvent::Warning => "Warning",
+                    NotificationEvent::Failure => "Attention",
+                }),
+                font_type: None,
+                wrap: false,
+            },
+            CardElement::FactSet { facts },
+        ];
+        if !content.errors.is_empty() {
+            body.push(CardElement::TextBlock {
+                text: excerpt(&content.errors.join("\n"), 2000),
+                weight: None,
+                size: None,
+                color: None,
+                font_type: Some("Monospace"),
+                wrap: true,
+            });
+        }
+
+        TeamsPayload {
+            kind: "message",
+            attachments: vec![TeamsAttachment {
+                content_type: "application/vnd.microsoft.card.adaptive",
+                content: AdaptiveCard {
+                    schema: "http://adaptivecards.io/schemas/adaptive-card.json",
+                    kind: "AdaptiveCard",
+                    version: "1.4",
+                    body,
+                },
+            }],
+        }
+    }
+}
+
+//limits enforced by the discord api, counted in characters
+const DISCORD_TITLE_LIMIT: usize = 256;
+const DISCORD_DESCRIPTION_LIMIT: usize = 4096;
+const DISCORD_FIELD_NAME_LIMIT: usize = 256;
+const DISCORD_FIELD_VALUE_LIMIT: usize = 1024;
+const DISCORD_FOOTER_LIMIT: usize = 2048;
+const DISCORD_MAX_FIELDS: usize = 25;
+const DISCORD_EMBED_LIMIT: usize = 6000;
+
+#[derive(Serialize)]
+struct DiscordPayload {
+    embeds: Vec<DiscordEmbed>,
+}
+
+#[derive(Serialize)]
+struct DiscordEmbed {
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    color: u32,
+    fields: Vec<DiscordField>,
+    footer: DiscordFooter,
+    timestamp: String,
+}
+
+#[derive(Serialize)]
+struct DiscordField {
+    name: String,
+    value: String,
+    inline: bool,
+}
+
+#[derive(Serialize)]
+struct DiscordFooter {
+    text: String,
+}
+
+impl DiscordPayload {
+    fn new(content: &NotificationContent) -> Self {
+        let title = excerpt(status_text(content), DISCORD_TITLE_LIMIT);
+        let footer = DiscordFooter {
+            text: excerpt(&format!("Host: {}", get_hostname()), DISCORD_FOOTER_LIMIT),
+        };
+        let color = match content.event() {
+            NotificationEvent::Success | NotificationEvent::Recovery => 0x2ecc71,
+            NotificationEvent::Warning => 0xf1c40f,
+            NotificationEvent::Failure => 0xe74c3c,
+        };
+
+        let mut fields: Vec<DiscordField> = content_fields(content)
+            .into_iter()
+            .filter(|(name, _)| name != "Host")
+            .take(DISCORD_MAX_FIELDS)
+            .map(|(name, value)| DiscordField {
+                inline: value.len() <= 64 && !value.contains('\n'),
+                name: excerpt(&name, DISCORD_FIELD_NAME_LIMIT),
+                //discord rejects empty field values
+                value: if value.trim().is_empty() {
+                    String::from("-")
+                } else {
+                    excerpt(&value, DISCORD_FIELD_VALUE_LIMIT)
+                },
+            })
+            .collect();
+
+        let field_chars = |fields: &[DiscordField]| -> usize {
+            fields
+                .iter()
+                .map(|field| field.name.chars().count() + field.value.chars().count())
+                .sum()
+        };
+        let fixed_chars = title.chars().count() + footer.text.chars().count();
+        while !fields.is_empty() && fixed_chars + field_chars(&fields) > DISCORD_EMBED_LIMIT {
+            fields.pop();
+        }
+
+        //the description gets whatever is left of the overall embed budget
+        let budget = DISCORD_EMBED_LIMIT
+            .saturating_sub(fixed_chars + field_chars(&fields))
+            .min(DISCORD_DESCRIPTION_LIMIT);
+        let description = if content.message.trim().is_empty() || budget == 0 {
+            None
         } else {
-            Err(Box::from("Error sending notification to discord"))
+            Some(excerpt(&content.message, budget))
+        };
+
+        DiscordPayload {
+            embeds: vec![DiscordEmbed {
+                title,
+                description,
+                color,
+                fields,
+                footer,
+                timestamp: chrono::Utc::now().to_rfc3339(),
+            }],
+        }
+    }
+}
+
+impl<'a> Notification for Discord<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let payload = DiscordPayload::new(self.content);
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "Discord", &RetryPolicy::BACKOFF, || {
+            client
+                .post(self.url)
+                .header("Accept", "application/json")
+                .json(&payload)
+                .send()
+        })
+    }
+}
+
+impl<'a> Notification for Webhook<'a> {
+    fn send_notification(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let template = self
+            .config
+            .template
+            .as_deref()
+            .unwrap_or(DEFAULT_WEBHOOK_TEMPLATE);
+        let body = minijinja::Environment::new().render_str(
+            template,
+            minijinja::context! {
+                status => match self.content.event() {
+                    NotificationEvent::Success | NotificationEvent::Recovery => "success",
+                    NotificationEvent::Warning => "warning",
+                    NotificationEvent::Failure => "failure",
+                },
+                success => self.content.success,
+                message => self.content.message,
+                destination => self.content.destination,
+                duration => self.content.duration.map(format_duration),
+                duration_seconds => self.content.duration.map(|d| d.as_secs()),
+                size => self.content.size,
+                size_human => self.content.size.map(format_size),
+                errors => self.content.errors,
+                destinations => self.content.outcomes,
+                stopped_containers => self.content.stopped_containers,
+                restarted_containers => self.content.restarted_containers,
+                warnings => self.content.warnings,
+                anomalies => self.content.anomalies,
+                hostname => get_hostname(),
+            },
+        )?;
+
+        let method = reqwest::Method::from_bytes(self.config.method.to_uppercase().as_bytes())?;
+        let client = reqwest::blocking::Client::builder()
+            .timeout(time::Duration::from_secs(10))
+            .build()?;
+
+        send_with_retry(self.logger, "webhook", &RetryPolicy::BACKOFF, || {
+            let mut request = client.request(method.clone(), &self.config.url);
+            for (name, value) in &self.config.headers {
+                request = request.header(name, value);
+            }
+            if !self
+                .config
+                .headers
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case("content-type"))
+            {
+                request = request.header("Content-Type", "application/json");
+            }
+            request.body(body.clone()).send()
+        })
+    }
+}
+
+fn configured_notifiers<'a>(
+    config: &'a DockerBackup,
+    content: &'a NotificationContent,
+    attachments: &EmailAttachments,
+) -> Vec<(&'static str, Box<dyn Notification + 'a>)> {
+    let mut notifiers: Vec<(&'static str, Box<dyn Notification + 'a>)> = Vec::new();
+
+    if let Some(gotify) = &config.gotify {
+        notifiers.push((
+            "gotify",
+            Box::new(Gotify {
+                content,
+                config: gotify,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(dc_url) = &config.discord_url {
+        notifiers.push((
+            "discord",
+            Box::new(Discord {
+                content,
+                url: dc_url,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(slack_url) = &config.slack_url {
+        notifiers.push((
+            "slack",
+            Box::new(Slack {
+                content,
+                url: slack_url,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(teams_url) = &config.teams_url {
+        notifiers.push((
+            "teams",
+            Box::new(Teams {
+                content,
+                url: teams_url,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(telegram) = &config.telegram {
+        notifiers.push((
+            "telegram",
+            Box::new(Telegram {
+                content,
+                config: telegram,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(matrix) = &config.matrix {
+        notifiers.push((
+            "matrix",
+            Box::new(Matrix {
+                content,
+                config: matrix,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(ntfy) = &config.ntfy {
+        notifiers.push((
+            "ntfy",
+            Box::new(Ntfy {
+                content,
+                config: ntfy,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    for webhook in &config.webhooks {
+        notifiers.push((
+            "webhook",
+            Box::new(Webhook {
+                content,
+                config: webhook,
+                logger: &config.logger,
+            }),
+        ));
+    }
+
+    if let Some(smtp) = &config.smtp {
+        notifiers.push((
+            "email",
+            Box::new(Email {
+                config: smtp,
+                content,
+                log: attachments.log.clone(),
+                report: attachments.report.clone(),
+            }),
+        ));
+    }
+
+    notifiers
+}
+
+fn dispatch_notifications(config: &DockerBackup, content: &NotificationContent, email: bool) {
+    let now = chrono::Local::now();
+    let attachments = if email {
+        EmailAttachments::of_run(config)
+    } else {
+        EmailAttachments::default()
+    };
+    for (idx, (name, notifier)) in configured_notifiers(config, content, &attachments)
+        .into_iter()
+        .enumerate()
+    {
+        if (name == "email") != email {
+            continue;
+        }
+        match config.notification_rule(name).decide(content, now) {
+            RuleDecision::Send => send_to_notifier(config, name, notifier.as_ref()),
+            RuleDecision::Skip => {}
+            RuleDecision::Delay(until) => {
+                config.logger.event(
+                    LogLevel::Info,
+                    "notification_delayed",
+                    content.destination.as_deref(),
+                    serde_json::json!({ "notifier": name, "until": until.to_rfc3339() }),
+                );
+                config
+                    .delayed_notifications
+                    .lock()
+                    .unwrap()
+                    .push(DelayedNotification {
+                        until,
+                        notifier: idx,
+                        name: name.to_string(),
+                        content: content.clone(),
+                        attachments: attachments.clone(),
+                    });
+            }
+        }
+    }
+}
+
+fn send_to_notifier(config: &DockerBackup, name: &str, notifier: &dyn Notification) {
+    match notifier.send_notification() {
+        Ok(()) => config.logger.event(
+            LogLevel::Info,
+            "notification_sent",
+            None,
+            serde_json::json!({ "notifier": name }),
+        ),
+        Err(e) => {
+            config.logger.log(
+                &format!("Error sending {} notification: {}", name, e),
+                LogLevel::Error,
+            );
+            config.logger.event(
+                LogLevel::Error,
+                "notification_failed",
+                None,
+                serde_json::json!({ "notifier": name, "error": e.to_string() }),
+            );
+        }
+    }
+}
+
+pub fn send_notifications(config: &DockerBackup, content: &NotificationContent) {
+    dispatch_notifications(config, content, false);
+}
+
+pub fn send_summary_notifications(config: &DockerBackup, content: &NotificationContent) {
+    dispatch_notifications(config, content, true);
+}
+
+/// Sends the notifications saved by earlier runs whose quiet hours have ended,
+/// the others are kept for a later run.
+pub fn send_delayed_notifications(config: &DockerBackup) {
+    let now = chrono::Local::now();
+    //due notifications are sent even when this run started inside the window again, a nightly
+    //run that always starts in quiet hours would never deliver them otherwise
+    let (due, pending): (Vec<DelayedNotification>, Vec<DelayedNotification>) =
+        read_state::<Vec<DelayedNotification>>(&config.state_dir, DELAYED_NOTIFICATIONS_STATE)
+            .into_iter()
+            .partition(|notification| notification.until <= now);
+    *config.delayed_notifications.lock().unwrap() = pending;
+
+    for notification in due {
+        //the notifier list is rebuilt from this run's arguments, skip entries that no longer match
+        match configured_notifiers(config, &notification.content, &notification.attachments)
+            .into_iter()
+            .nth(notification.notifier)
+        {
+            Some((name, notifier)) if name == notification.name => {
+                send_to_notifier(config, name, notifier.as_ref())
+            }
+            _ => config.logger.log(
+                &format!(
+                    "Dropping delayed {} notification, the notifier is no longer configured",
+                    notification.name
+                ),
+                LogLevel::Warning,
+            ),
         }
     }
 }
 
-pub fn send_notification<T: Notification>(
-    notification: T,
-) -> Result<(), Box<dyn std::error::Error>> {
-    notification.send_notification()
+/// Saves the notifications still held back by quiet hours for the next run.
+pub fn save_delayed_notifications(config: &DockerBackup) {
+    let delayed = config.delayed_notifications.lock().unwrap();
+    if let Err(err) = write_state(&config.state_dir, DELAYED_NOTIFICATIONS_STATE, &*delayed) {
+        config.logger.log(
+            &format!("Failed to save delayed notifications: {}", err),
+            LogLevel::Warning,
+        );
+    } else if let Some(until) = delayed.iter().map(|notification| notification.until).min() {
+        config.logger.log(
+            &format!(
+                "Quiet hours, {} notification(s) will be sent by the first run after {}",
+                delayed.len(),
+                until.format("%Y-%m-%d %H:%M")
+            ),
+            LogLevel::Info,
+        );
+    }
 }
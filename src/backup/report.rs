@@ -0,0 +1,271 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::backup::backup_result::{BackupError, RunStatus, RunSummary};
+use crate::backup::state::{read_state, write_state};
+use crate::backup::utils::{format_duration, format_size, get_hostname};
+
+const REPORT_INDEX: &str = "index.json";
+const BIGGEST_VOLUMES: usize = 10;
+
+const STYLE: &str = r#"<style>
+body { font-family: -apple-system, "Segoe UI", Roboto, sans-serif; color: #222; margin: 2rem auto; max-width: 960px; padding: 0 1rem; }
+h1 { font-size: 1.5rem; margin-bottom: 0.25rem; }
+h2 { font-size: 1.1rem; margin-top: 2rem; border-bottom: 1px solid #ddd; padding-bottom: 0.25rem; }
+.meta { color: #666; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.4rem 0.6rem; border-bottom: 1px solid #eee; vertical-align: top; }
+th { background: #f6f6f6; }
+td.number { text-align: right; white-space: nowrap; }
+.status { display: inline-block; padding: 0.1rem 0.5rem; border-radius: 0.25rem; color: #fff; font-size: 0.85rem; }
+.success, .ok { background: #2ecc71; }
+.partial_failure, .interrupted { background: #e67e22; }
+.failure, .preflight_failure, .failed { background: #e74c3c; }
+.bar { background: #3498db; height: 0.6rem; border-radius: 0.2rem; }
+pre { white-space: pre-wrap; word-break: break-word; background: #fafafa; border: 1px solid #eee; padding: 0.5rem; margin: 0; font-size: 0.85rem; }
+</style>"#;
+
+const REPORT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Docker backup {{ started_at }} on {{ hostname }}</title>
+{{ style|safe }}
+</head>
+<body>
+<h1>Docker backup on {{ hostname }} <span class="status {{ status }}">{{ status_label }}</span></h1>
+<p class="meta">Started {{ started_at }}, finished {{ finished_at }}, run time {{ duration }}. <a href="index.html">All runs</a></p>
+
+<h2>Destinations</h2>
+<table>
+<tr><th>Destination</th><th>Status</th><th>Run time</th><th>Size</th></tr>
+{% for destination in destinations %}
+<tr>
+<td>{{ destination.name }}</td>
+<td><span class="status {{ 'ok' if destination.success else 'failed' }}">{{ 'OK' if destination.success else 'Failed' }}</span></td>
+<td class="number">{{ destination.duration }}</td>
+<td class="number">{{ destination.size }}</td>
+</tr>
+{% if destination.error %}<tr><td colspan="4"><pre>{{ destination.error }}</pre></td></tr>{% endif %}
+{% endfor %}
+</table>
+
+{% if volumes %}
+<h2>Biggest volumes</h2>
+<table>
+<tr><th>Volume</th><th>Size</th><th style="width: 40%"></th></tr>
+{% for volume in volumes %}
+<tr><td>{{ volume.name }}</td><td class="number">{{ volume.size }}</td><td><div class="bar" style="width: {{ volume.percent }}%"></div></td></tr>
+{% endfor %}
+</table>
+<p class="meta">{{ total_size }} in {{ volume_count }} volume{{ "s" if volume_count != 1 }}.</p>
+{% endif %}
+
+<h2>Containers</h2>
+<table>
+<tr><th>Stopped</th><td>{{ stopped_containers|join(", ") if stopped_containers else "None" }}</td></tr>
+<tr><th>Started again</th><td>{{ restarted_containers|join(", ") if restarted_containers else "None" }}</td></tr>
+</table>
+
+{% if warnings %}
+<h2>Warnings</h2>
+{% for warning in warnings %}<pre>{{ warning }}</pre>{% endfor %}
+{% endif %}
+
+{% if anomalies %}
+<h2>Size anomalies</h2>
+{% for anomaly in anomalies %}<pre>{{ anomaly }}</pre>{% endfor %}
+{% endif %}
+</body>
+</html>
+"#;
+
+const INDEX_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Docker backups on {{ hostname }}</title>
+{{ style|safe }}
+</head>
+<body>
+<h1>Docker backups on {{ hostname }}</h1>
+<table>
+<tr><th>Started</th><th>Status</th><th>Destinations</th><th>Run time</th><th></th></tr>
+{% for run in runs %}
+<tr>
+<td>{{ run.started_at }}</td>
+<td><span class="status {{ run.status }}">{{ run.status_label }}</span></td>
+<td class="number">{{ run.succeeded }} of {{ run.destinations }} succeeded</td>
+<td class="number">{{ run.duration }}</td>
+<td><a href="{{ run.file }}">Report</a></td>
+</tr>
+{% endfor %}
+</table>
+</body>
+</html>
+"#;
+
+/// One line of the index page, kept in `index.json` in the report directory.
+#[derive(Serialize, Deserialize)]
+struct ReportEntry {
+    file: String,
+    started_at: String,
+    status: String,
+    status_label: String,
+    succeeded: usize,
+    destinations: usize,
+    duration: String,
+}
+
+#[derive(Serialize)]
+struct DestinationRow {
+    name: String,
+    success: bool,
+    duration: String,
+    size: String,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct VolumeRow {
+    name: String,
+    size: String,
+    percent: u64,
+}
+
+fn status_label(status: RunStatus) -> &'static str {
+    match status {
+        RunStatus::Success => "Success",
+        RunStatus::PartialFailure => "Partial failure",
+        RunStatus::Failure => "Failed",
+        RunStatus::PreflightFailure => "Preflight failure",
+        RunStatus::Interrupted => "Interrupted",
+    }
+}
+
+/// Writes the HTML report of a run and updates the index page, returning the report.
+pub fn write_report(
+    dir: &Path,
+    started_at: DateTime<Local>,
+    summary: &RunSummary,
+    status: RunStatus,
+    volume_sizes: &BTreeMap<String, u64>,
+) -> Result<String, BackupError> {
+    let hostname = get_hostname();
+    let file = format!("{}.html", started_at.format("%Y-%m-%d_%H-%M-%S"));
+
+    let destinations: Vec<DestinationRow> = summary
+        .results
+        .iter()
+        .map(|result| match result {
+            Ok(success) => DestinationRow {
+                name: success.destination.clone(),
+                success: true,
+                duration: format_duration(success.duration),
+                size: format_size(success.size),
+                error: None,
+            },
+            Err(err) => DestinationRow {
+                name: err
+                    .destination
+                    .clone()
+                    .unwrap_or_else(|| String::from("backup")),
+                success: false,
+                duration: String::new(),
+                size: String::new(),
+                error: Some(err.message.clone()),
+            },
+        })
+        .collect();
+
+    let mut volumes: Vec<(&String, &u64)> = volume_sizes.iter().collect();
+    volumes.sort_by(|a, b| b.1.cmp(a.1));
+    let biggest = volumes.first().map(|(_, size)| **size).unwrap_or_default();
+    let volume_rows: Vec<VolumeRow> = volumes
+        .iter()
+        .take(BIGGEST_VOLUMES)
+        .map(|(name, size)| VolumeRow {
+            name: name.to_string(),
+            size: format_size(**size),
+            percent: (**size * 100).checked_div(biggest).unwrap_or_default(),
+        })
+        .collect();
+
+    let mut env = minijinja::Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    //the .html names turn on html escaping of every value
+    env.add_template("report.html", REPORT_TEMPLATE)
+        .map_err(report_error)?;
+    env.add_template("index.html", INDEX_TEMPLATE)
+        .map_err(report_error)?;
+
+    let report = env
+        .get_template("report.html")
+        .and_then(|template| {
+            template.render(minijinja::context! {
+                style => STYLE,
+                hostname => hostname,
+                status => status.name(),
+                status_label => status_label(status),
+                started_at => started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                finished_at => Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                duration => format_duration(summary.duration),
+                destinations => destinations,
+                volumes => volume_rows,
+                volume_count => volume_sizes.len(),
+                total_size => format_size(volume_sizes.values().sum()),
+                stopped_containers => summary.stopped_containers,
+                restarted_containers => summary.restarted_containers,
+                warnings => summary.warnings,
+                anomalies => summary.anomalies,
+            })
+        })
+        .map_err(report_error)?;
+
+    fs::create_dir_all(dir).map_err(|e| {
+        BackupError::new(&format!(
+            "Failed to create report directory {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+    fs::write(dir.join(&file), &report)?;
+
+    let mut runs: Vec<ReportEntry> = read_state(dir, REPORT_INDEX);
+    //reports removed by hand disappear from the index
+    runs.retain(|run| run.file != file && dir.join(&run.file).exists());
+    runs.insert(
+        0,
+        ReportEntry {
+            file,
+            started_at: started_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            status: status.name().to_string(),
+            status_label: status_label(status).to_string(),
+            succeeded: summary.results.iter().filter(|r| r.is_ok()).count(),
+            destinations: summary.results.len(),
+            duration: format_duration(summary.duration),
+        },
+    );
+    write_state(dir, REPORT_INDEX, &runs)?;
+
+    let index = env
+        .get_template("index.html")
+        .and_then(|template| {
+            template.render(minijinja::context! {
+                style => STYLE,
+                hostname => get_hostname(),
+                runs => runs,
+            })
+        })
+        .map_err(report_error)?;
+    fs::write(dir.join("index.html"), index)?;
+
+    Ok(report)
+}
+
+fn report_error(error: minijinja::Error) -> BackupError {
+    BackupError::new(&format!("Failed to render report: {}", error))
+}
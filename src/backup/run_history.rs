@@ -0,0 +1,392 @@
+use std::{fs, path::Path, time::Duration};
+
+use chrono::{DateTime, Local};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::backup::backup_result::{BackupError, BackupSuccess, RunSummary};
+use crate::backup::utils::{format_duration, format_size};
+
+pub const HISTORY_DB: &str = "history.db";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    duration_seconds REAL NOT NULL,
+    stopped_containers TEXT NOT NULL,
+    restarted_containers TEXT NOT NULL,
+    warnings TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS destinations (
+    run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    destination TEXT,
+    success INTEGER NOT NULL,
+    size INTEGER,
+    duration_seconds REAL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS destinations_destination ON destinations(destination, run_id);
+";
+
+#[derive(Serialize)]
+pub struct DestinationRecord {
+    /// Empty for errors that happened before any destination was started.
+    pub destination: Option<String>,
+    pub success: bool,
+    pub size: Option<u64>,
+    pub duration_seconds: Option<f64>,
+    pub error: Option<String>,
+}
+
+impl From<&Result<BackupSuccess, BackupError>> for DestinationRecord {
+    fn from(result: &Result<BackupSuccess, BackupError>) -> Self {
+        match result {
+            Ok(success) => DestinationRecord {
+                destination: Some(success.destination.clone()),
+                success: true,
+                size: Some(success.size),
+                duration_seconds: Some(success.duration.as_secs_f64()),
+                error: None,
+            },
+            Err(err) => DestinationRecord {
+                destination: err.destination.clone(),
+                success: false,
+                size: None,
+                duration_seconds: None,
+                error: Some(err.message.clone()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RunRecord {
+    pub id: i64,
+    pub started_at: String,
+    pub finished_at: String,
+    pub success: bool,
+    pub duration_seconds: f64,
+    pub stopped_containers: Vec<String>,
+    pub restarted_containers: Vec<String>,
+    pub warnings: Vec<String>,
+    pub destinations: Vec<DestinationRecord>,
+}
+
+#[derive(Serialize)]
+pub struct DestinationStatus {
+    pub destination: String,
+    pub last_run: String,
+    pub last_success: Option<String>,
+    pub success: bool,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Every run and its per-destination results, stored in `history.db` in the state directory.
+pub struct RunHistory {
+    connection: Connection,
+}
+
+impl RunHistory {
+    pub fn open(state_dir: &Path) -> Result<Self, BackupError> {
+        fs::create_dir_all(state_dir).map_err(|e| {
+            BackupError::new(&format!(
+                "Failed to create state directory {}: {}",
+                state_dir.display(),
+                e
+            ))
+        })?;
+        let path = state_dir.join(HISTORY_DB);
+        let connection = Connection::open(&path)
+            .and_then(|connection| {
+                connection.execute_batch(SCHEMA)?;
+                Ok(connection)
+            })
+            .map_err(|e| {
+                BackupError::new(&format!(
+                    "Failed to open run history {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        Ok(Self { connection })
+    }
+
+    pub fn record(
+        &mut self,
+        started_at: DateTime<Local>,
+        summary: &RunSummary,
+        success: bool,
+    ) -> Result<(), BackupError> {
+        let transaction = self.connection.transaction().map_err(history_error)?;
+        transaction
+            .execute(
+                "INSERT INTO runs (started_at, finished_at, success, duration_seconds, stopped_containers, restarted_containers, warnings)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    started_at.to_rfc3339(),
+                    Local::now().to_rfc3339(),
+                    success,
+                    summary.duration.as_secs_f64(),
+                    serde_json::to_string(&summary.stopped_containers).unwrap(),
+                    serde_json::to_string(&summary.restarted_containers).unwrap(),
+                    serde_json::to_string(&summary.warnings).unwrap(),
+                ],
+            )
+            .map_err(history_error)?;
+        let run_id = transaction.last_insert_rowid();
+        for result in summary.results {
+            let record = DestinationRecord::from(result);
+            transaction
+                .execute(
+                    "INSERT INTO destinations (run_id, destination, success, size, duration_seconds, error)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        run_id,
+                        record.destination,
+                        record.success,
+                        record.size,
+                        record.duration_seconds,
+                        record.error,
+                    ],
+                )
+                .map_err(history_error)?;
+        }
+        transaction.commit().map_err(history_error)
+    }
+
+    /// Most recent runs first, optionally only the ones that included the destination.
+    pub fn runs(
+        &self,
+        limit: usize,
+        destination: Option<&str>,
+    ) -> Result<Vec<RunRecord>, BackupError> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT id, started_at, finished_at, success, duration_seconds, stopped_containers, restarted_containers, warnings
+                 FROM runs
+                 WHERE ?1 IS NULL OR id IN (SELECT run_id FROM destinations WHERE destination = ?1)
+                 ORDER BY id DESC LIMIT ?2",
+            )
+            .map_err(history_error)?;
+        let mut runs = statement
+            .query_map(params![destination, limit as i64], |row| {
+                Ok(RunRecord {
+                    id: row.get(0)?,
+                    started_at: row.get(1)?,
+                    finished_at: row.get(2)?,
+                    success: row.get(3)?,
+                    duration_seconds: row.get(4)?,
+                    stopped_containers: parse_list(&row.get::<_, String>(5)?),
+                    restarted_containers: parse_list(&row.get::<_, String>(6)?),
+                    warnings: parse_list(&row.get::<_, String>(7)?),
+                    destinations: Vec::new(),
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<RunRecord>, _>>())
+            .map_err(history_error)?;
+
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT destination, success, size, duration_seconds, error
+                 FROM destinations WHERE run_id = ?1 ORDER BY rowid",
+            )
+            .map_err(history_error)?;
+        for run in &mut runs {
+            run.destinations = statement
+                .query_map([run.id], |row| {
+                    Ok(DestinationRecord {
+                        destination: row.get(0)?,
+                        success: row.get(1)?,
+                        size: row.get(2)?,
+                        duration_seconds: row.get(3)?,
+                        error: row.get(4)?,
+                    })
+                })
+                .and_then(|rows| rows.collect::<Result<Vec<DestinationRecord>, _>>())
+                .map_err(history_error)?;
+        }
+        Ok(runs)
+    }
+
+    /// Latest result and last success of every destination that was ever backed up.
+    pub fn status(&self) -> Result<Vec<DestinationStatus>, BackupError> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT d.destination, r.started_at, d.success, d.size, d.error
+                 FROM destinations d JOIN runs r ON r.id = d.run_id
+                 WHERE d.rowid IN (SELECT MAX(rowid) FROM destinations WHERE destination IS NOT NULL GROUP BY destination)
+                 ORDER BY d.destination",
+            )
+            .map_err(history_error)?;
+        let mut statuses = statement
+            .query_map([], |row| {
+                Ok(DestinationStatus {
+                    destination: row.get(0)?,
+                    last_run: row.get(1)?,
+                    last_success: None,
+                    success: row.get(2)?,
+                    size: row.get(3)?,
+                    error: row.get(4)?,
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<DestinationStatus>, _>>())
+            .map_err(history_error)?;
+
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT r.started_at FROM destinations d JOIN runs r ON r.id = d.run_id
+                 WHERE d.destination = ?1 AND d.success ORDER BY r.id DESC LIMIT 1",
+            )
+            .map_err(history_error)?;
+        for status in &mut statuses {
+            status.last_success = statement
+                .query_row([&status.destination], |row| row.get(0))
+                .optional()
+                .map_err(history_error)?;
+        }
+        Ok(statuses)
+    }
+}
+
+fn history_error(error: rusqlite::Error) -> BackupError {
+    BackupError::new(&format!("Run history error: {}", error))
+}
+
+fn parse_list(list: &str) -> Vec<String> {
+    serde_json::from_str(list).unwrap_or_default()
+}
+
+fn format_time(time: &str) -> String {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|_| time.to_string())
+}
+
+fn format_status(success: bool) -> String {
+    String::from(if success { "ok" } else { "failed" })
+}
+
+//only the first line of an error fits in a table cell
+fn format_error(error: &Option<String>) -> String {
+    let line = error
+        .as_deref()
+        .and_then(|error| error.lines().next())
+        .unwrap_or("");
+    if line.chars().count() > 60 {
+        format!("{}…", line.chars().take(59).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Aligns the columns of a plain text table, the last column isn't padded.
+fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<String>| {
+        let mut line = String::new();
+        for (idx, cell) in cells.iter().enumerate() {
+            if idx + 1 < cells.len() {
+                line.push_str(&format!("{:<width$}  ", cell, width = widths[idx]));
+            } else {
+                line.push_str(cell);
+            }
+        }
+        line.trim_end().to_string()
+    };
+    let mut lines = vec![format_row(headers.iter().map(|h| h.to_string()).collect())];
+    lines.extend(rows.iter().map(|row| format_row(row.clone())));
+    lines.join("\n")
+}
+
+pub fn runs_table(runs: &[RunRecord]) -> String {
+    let mut rows = Vec::new();
+    for run in runs {
+        //runs that failed before starting a destination still get a row, with the error
+        //that stopped them kept as the run's warning
+        if run.destinations.is_empty() {
+            rows.push(vec![
+                run.id.to_string(),
+                format_time(&run.started_at),
+                String::from("-"),
+                format_status(run.success),
+                format_duration(Duration::from_secs_f64(run.duration_seconds)),
+                String::new(),
+                format_error(&run.warnings.first().cloned()),
+            ]);
+        }
+        for destination in &run.destinations {
+            rows.push(vec![
+                run.id.to_string(),
+                format_time(&run.started_at),
+                destination
+                    .destination
+                    .as_deref()
+                    .unwrap_or("-")
+                    .to_string(),
+                format_status(destination.success),
+                destination
+                    .duration_seconds
+                    .map(|duration| format_duration(Duration::from_secs_f64(duration)))
+                    .unwrap_or_default(),
+                destination.size.map(format_size).unwrap_or_default(),
+                format_error(&destination.error),
+            ]);
+        }
+    }
+    format_table(
+        &[
+            "RUN",
+            "STARTED",
+            "DESTINATION",
+            "STATUS",
+            "DURATION",
+            "SIZE",
+            "ERROR",
+        ],
+        &rows,
+    )
+}
+
+pub fn status_table(statuses: &[DestinationStatus]) -> String {
+    let rows: Vec<Vec<String>> = statuses
+        .iter()
+        .map(|status| {
+            vec![
+                status.destination.clone(),
+                format_time(&status.last_run),
+                format_status(status.success),
+                status
+                    .last_success
+                    .as_deref()
+                    .map(format_time)
+                    .unwrap_or(String::from("never")),
+                status.size.map(format_size).unwrap_or_default(),
+                format_error(&status.error),
+            ]
+        })
+        .collect();
+    format_table(
+        &[
+            "DESTINATION",
+            "LAST RUN",
+            "STATUS",
+            "LAST SUCCESS",
+            "SIZE",
+            "ERROR",
+        ],
+        &rows,
+    )
+}
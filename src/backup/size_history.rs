@@ -0,0 +1,84 @@
+use std::{collections::BTreeMap, path::Path};
+
+use crate::backup::backup_result::BackupError;
+use crate::backup::state::{read_state, write_state};
+use crate::backup::utils::format_size;
+
+const VOLUME_SIZES_STATE: &str = "volume_sizes.json";
+//small volumes like config directories routinely double, don't warn about them
+const MIN_AVERAGE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SizeThresholds {
+    /// Warn when a volume is this many percent smaller than its recent average, 0 disables it.
+    pub shrink: u64,
+    /// Warn when a volume is this many percent larger than its recent average, 0 disables it.
+    pub growth: u64,
+    /// Number of past runs kept per volume for the average.
+    pub history: usize,
+}
+
+/// Compares the current volume sizes with the recent ones and records them for the next run.
+/// Returns an anomaly for every volume that changed too much or disappeared.
+pub fn check_volume_sizes(
+    state_dir: &Path,
+    sizes: &BTreeMap<String, u64>,
+    excluded_volumes: &[String],
+    thresholds: SizeThresholds,
+) -> Result<Vec<String>, BackupError> {
+    let mut history: BTreeMap<String, Vec<u64>> = read_state(state_dir, VOLUME_SIZES_STATE);
+    let mut anomalies = Vec::new();
+
+    for (volume, &size) in sizes {
+        let recent = history.entry(volume.clone()).or_default();
+        if !recent.is_empty() {
+            let average = recent.iter().sum::<u64>() / recent.len() as u64;
+            if average >= MIN_AVERAGE_SIZE {
+                let change = (size as f64 - average as f64) / average as f64 * 100.0;
+                if thresholds.shrink > 0 && -change >= thresholds.shrink as f64 {
+                    anomalies.push(format!(
+                        "Volume {} shrank by {:.0}% to {} compared to its recent average of {}",
+                        volume,
+                        -change,
+                        format_size(size),
+                        format_size(average)
+                    ));
+                } else if thresholds.growth > 0 && change >= thresholds.growth as f64 {
+                    anomalies.push(format!(
+                        "Volume {} grew by {:.0}% to {} compared to its recent average of {}",
+                        volume,
+                        change,
+                        format_size(size),
+                        format_size(average)
+                    ));
+                }
+            }
+        }
+        recent.push(size);
+        if recent.len() > thresholds.history {
+            recent.drain(..recent.len() - thresholds.history);
+        }
+    }
+
+    //excluded volumes are kept, they didn't disappear and may be included again
+    let disappeared: Vec<String> = history
+        .keys()
+        .filter(|volume| !sizes.contains_key(*volume) && !excluded_volumes.contains(volume))
+        .cloned()
+        .collect();
+    for volume in disappeared {
+        if let Some(last) = history
+            .remove(&volume)
+            .and_then(|sizes| sizes.last().copied())
+        {
+            anomalies.push(format!(
+                "Volume {} disappeared, it was {} in the last run",
+                volume,
+                format_size(last)
+            ));
+        }
+    }
+
+    write_state(state_dir, VOLUME_SIZES_STATE, &history)?;
+    Ok(anomalies)
+}
@@ -0,0 +1,256 @@
+use std::{
+    error::Error,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::ssl::{SslConnector, SslMethod};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const IO_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SmtpSecurity {
+    StartTls,
+    Tls,
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn from_str(security: &str) -> Result<Self, String> {
+        match security.to_lowercase().as_str() {
+            "starttls" => Ok(SmtpSecurity::StartTls),
+            "tls" => Ok(SmtpSecurity::Tls),
+            "none" => Ok(SmtpSecurity::None),
+            _ => Err(String::from("Unsupported smtp security")),
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    pub attach_log: bool,
+    pub attach_report: bool,
+}
+
+trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+/// Minimal SMTP client, just enough to deliver a single message.
+struct SmtpClient {
+    stream: BufReader<Box<dyn Connection>>,
+}
+
+impl SmtpClient {
+    fn new(stream: Box<dyn Connection>) -> Self {
+        SmtpClient {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    fn read_response(&mut self, expected: u16) -> Result<(), Box<dyn Error>> {
+        let mut response = String::new();
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line)? == 0 {
+                return Err(Box::from("SMTP server closed the connection"));
+            }
+            response.push_str(&line);
+            //the last line of a reply has a space after the code, others a dash
+            if line.len() < 4 || line.as_bytes()[3] != b'-' {
+                break;
+            }
+        }
+
+        match response.get(0..3).and_then(|code| code.parse::<u16>().ok()) {
+            Some(code) if code == expected => Ok(()),
+            _ => Err(Box::from(format!(
+                "Unexpected SMTP response: {}",
+                response.trim()
+            ))),
+        }
+    }
+
+    fn command(&mut self, command: &str, expected: u16) -> Result<(), Box<dyn Error>> {
+        let stream = self.stream.get_mut();
+        stream.write_all(command.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+        self.read_response(expected)
+    }
+}
+
+/// Connects to the first reachable address of the server, so an unresponsive server can't stall the run.
+fn connect(config: &SmtpConfig) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for address in (config.host.as_str(), config.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(tcp) => return Ok(tcp),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No address found for SMTP server {}", config.host),
+        )
+    }))
+}
+
+pub fn send_mail(config: &SmtpConfig, message: &str) -> Result<(), Box<dyn Error>> {
+    let tcp = connect(config)?;
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+    let hostname = crate::backup::utils::get_hostname();
+
+    let mut client = match config.security {
+        SmtpSecurity::None => {
+            let mut client = SmtpClient::new(Box::new(tcp));
+            client.read_response(220)?;
+            client
+        }
+        SmtpSecurity::Tls => {
+            let connector = SslConnector::builder(SslMethod::tls())?.build();
+            let mut client = SmtpClient::new(Box::new(connector.connect(&config.host, tcp)?));
+            client.read_response(220)?;
+            client
+        }
+        SmtpSecurity::StartTls => {
+            let mut plain = SmtpClient::new(Box::new(tcp.try_clone()?));
+            plain.read_response(220)?;
+            plain.command(&format!("EHLO {}", hostname), 250)?;
+            plain.command("STARTTLS", 220)?;
+            let connector = SslConnector::builder(SslMethod::tls())?.build();
+            SmtpClient::new(Box::new(connector.connect(&config.host, tcp)?))
+        }
+    };
+
+    client.command(&format!("EHLO {}", hostname), 250)?;
+
+    if let Some(username) = &config.username {
+        let credentials = format!(
+            "\0{}\0{}",
+            username,
+            config.password.as_deref().unwrap_or_default()
+        );
+        client.command(&format!("AUTH PLAIN {}", STANDARD.encode(credentials)), 235)?;
+    }
+
+    client.command(&format!("MAIL FROM:<{}>", config.from), 250)?;
+    for recipient in &config.to {
+        client.command(&format!("RCPT TO:<{}>", recipient), 250)?;
+    }
+    client.command("DATA", 354)?;
+
+    let mut data = String::new();
+    for line in message.lines() {
+        //dot-stuffing, a single dot on a line ends the message
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push('.');
+    client.command(&data, 250)?;
+    client.command("QUIT", 221)?;
+    Ok(())
+}
+
+/// A file attached to an email: file name, content type and content.
+pub struct Attachment<'a> {
+    pub file_name: &'a str,
+    pub content_type: &'a str,
+    pub content: &'a str,
+}
+
+pub fn build_message(
+    config: &SmtpConfig,
+    subject: &str,
+    body: &str,
+    attachments: &[Attachment],
+) -> String {
+    let date = chrono::Local::now();
+    let mut message = format!(
+        "From: {}\nTo: {}\nSubject: {}\nDate: {}\nMessage-ID: <{}.dockerbackup@{}>\nMIME-Version: 1.0\n",
+        config.from,
+        config.to.join(", "),
+        encode_header(subject),
+        date.to_rfc2822(),
+        date.timestamp_nanos_opt().unwrap_or_default(),
+        crate::backup::utils::get_hostname()
+    );
+
+    if attachments.is_empty() {
+        message.push_str(
+            "Content-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: base64\n\n",
+        );
+        push_base64(&mut message, body);
+        return message;
+    }
+
+    let boundary = format!("dockerbackup-{}", date.timestamp());
+    message.push_str(&format!(
+        "Content-Type: multipart/mixed; boundary=\"{}\"\n\n--{}\nContent-Type: text/plain; charset=utf-8\nContent-Transfer-Encoding: base64\n\n",
+        boundary, boundary
+    ));
+    push_base64(&mut message, body);
+    for attachment in attachments {
+        message.push_str(&format!(
+            "--{}\nContent-Type: {}; charset=utf-8; name=\"{}\"\nContent-Disposition: attachment; filename=\"{}\"\nContent-Transfer-Encoding: base64\n\n",
+            boundary, attachment.content_type, attachment.file_name, attachment.file_name
+        ));
+        push_base64(&mut message, attachment.content);
+    }
+    message.push_str(&format!("--{}--\n", boundary));
+    message
+}
+
+/// Appends `content` base64 encoded in 76 character lines, so long log lines can't exceed the
+/// 998 octet line limit of SMTP.
+fn push_base64(message: &mut String, content: &str) {
+    let encoded = STANDARD.encode(content);
+    for chunk in encoded.as_bytes().chunks(76) {
+        message.push_str(&String::from_utf8_lossy(chunk));
+        message.push('\n');
+    }
+}
+
+/// Encodes a header value with non-ASCII characters as RFC 2047 encoded-words, split so every
+/// word stays within the 75 character limit without cutting a character in half.
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    //45 bytes encode to 60 base64 characters, plus 12 for =?utf-8?B??=
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in value.chars() {
+        if word.len() + c.len_utf8() > 45 {
+            words.push(format!("=?utf-8?B?{}?=", STANDARD.encode(&word)));
+            word.clear();
+        }
+        word.push(c);
+    }
+    words.push(format!("=?utf-8?B?{}?=", STANDARD.encode(&word)));
+    //adjacent encoded-words are folded onto continuation lines
+    words.join("\n ")
+}
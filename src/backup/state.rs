@@ -0,0 +1,36 @@
+use std::{fs, path::Path};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::backup::backup_result::BackupError;
+
+/// Reads a JSON state file, falling back to the default when it's missing or unreadable.
+pub fn read_state<T: DeserializeOwned + Default>(state_dir: &Path, name: &str) -> T {
+    fs::read_to_string(state_dir.join(name))
+        .ok()
+        .and_then(|state| serde_json::from_str(&state).ok())
+        .unwrap_or_default()
+}
+
+pub fn write_state<T: Serialize>(
+    state_dir: &Path,
+    name: &str,
+    state: &T,
+) -> Result<(), BackupError> {
+    fs::create_dir_all(state_dir).map_err(|e| {
+        BackupError::new(&format!(
+            "Failed to create state directory {}: {}",
+            state_dir.display(),
+            e
+        ))
+    })?;
+    let state = serde_json::to_string_pretty(state)
+        .map_err(|e| BackupError::new(&format!("Failed to serialize state: {}", e)))?;
+
+    //write to a temporary file first so an interrupted run never leaves a truncated state
+    let path = state_dir.join(name);
+    let tmp_path = state_dir.join(format!(".{}.tmp", name));
+    fs::write(&tmp_path, state)?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::{fs, path::Path};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::backup::backup_result::{BackupError, RunStatus, RunSummary};
+use crate::backup::run_history::DestinationRecord;
+
+/// Machine readable result of a run written with `--summary-json`.
+#[derive(Serialize)]
+struct SummaryFile<'a> {
+    status: &'static str,
+    exit_code: i32,
+    started_at: String,
+    finished_at: String,
+    duration_seconds: f64,
+    destinations: Vec<DestinationRecord>,
+    stopped_containers: &'a [String],
+    restarted_containers: &'a [String],
+    warnings: &'a [String],
+    anomalies: &'a [String],
+    /// Set when the run failed before any destination was started.
+    error: Option<&'a str>,
+}
+
+pub fn write_summary_file(
+    path: &Path,
+    status: RunStatus,
+    started_at: DateTime<Local>,
+    summary: Option<&RunSummary>,
+    error: Option<&str>,
+) -> Result<(), BackupError> {
+    let finished_at = Local::now();
+    let summary_file = SummaryFile {
+        status: status.name(),
+        exit_code: status.code(),
+        started_at: started_at.to_rfc3339(),
+        finished_at: finished_at.to_rfc3339(),
+        duration_seconds: match summary {
+            Some(summary) => summary.duration.as_secs_f64(),
+            None => (finished_at - started_at).num_milliseconds() as f64 / 1000.0,
+        },
+        destinations: summary
+            .map(|summary| {
+                summary
+                    .results
+                    .iter()
+                    .map(DestinationRecord::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        stopped_containers: summary.map_or(&[], |summary| &summary.stopped_containers),
+        restarted_containers: summary.map_or(&[], |summary| &summary.restarted_containers),
+        warnings: summary.map_or(&[], |summary| &summary.warnings),
+        anomalies: summary.map_or(&[], |summary| &summary.anomalies),
+        error,
+    };
+    let json = serde_json::to_string_pretty(&summary_file)
+        .map_err(|e| BackupError::new(&format!("Failed to serialize run summary: {}", e)))?;
+
+    //scripts may poll for the file, so never let them see it half written
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, json)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(|e| {
+            BackupError::new(&format!(
+                "Failed to write run summary to {}: {}",
+                path.display(),
+                e
+            ))
+        })
+}
@@ -0,0 +1,237 @@
+use std::{net::UdpSocket, sync::Mutex};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+
+use crate::backup::backup_result::BackupError;
+use crate::backup::logger::{LogRecord, LogSink};
+use crate::backup::utils::get_hostname;
+
+const APP_NAME: &str = "dockerbackup";
+//enterprise number reserved for documentation by RFC 5612
+const SD_ID: &str = "dockerbackup@32473";
+//longest datagram accepted by most syslog daemons
+const MAX_SYSLOG_MESSAGE: usize = 8192;
+
+enum Transport {
+    #[cfg(unix)]
+    Unix(UnixDatagram, String),
+    Udp(UdpSocket),
+}
+
+/// RFC 5424 syslog over a unix datagram socket (`unix:///dev/log`) or UDP (`udp://host:514`).
+pub struct SyslogSink {
+    transport: Mutex<Transport>,
+    facility: u8,
+    hostname: String,
+}
+
+impl SyslogSink {
+    pub fn connect(url: &str, facility: u8) -> Result<Self, BackupError> {
+        let transport = if let Some(address) = url.strip_prefix("udp://") {
+            let address = if address.contains(':') {
+                address.to_string()
+            } else {
+                format!("{}:514", address)
+            };
+            let socket = UdpSocket::bind("0.0.0.0:0")?;
+            socket.connect(&address).map_err(|e| {
+                BackupError::new(&format!("Failed to connect to syslog {}: {}", address, e))
+            })?;
+            Transport::Udp(socket)
+        } else if let Some(path) = url.strip_prefix("unix://") {
+            unix_transport(path)?
+        } else {
+            return Err(BackupError::new(&format!(
+                "Unsupported syslog url {}, expected unix:///path or udp://host[:port]",
+                url
+            )));
+        };
+        Ok(Self {
+            transport: Mutex::new(transport),
+            facility,
+            hostname: get_hostname(),
+        })
+    }
+
+    fn format(&self, record: &LogRecord) -> String {
+        let mut structured_data = format!("[{} event=\"{}\"", SD_ID, sd_escape(record.event));
+        if let Some(destination) = record.destination {
+            structured_data.push_str(&format!(" destination=\"{}\"", sd_escape(destination)));
+        }
+        structured_data.push(']');
+
+        let message = format!(
+            "<{}>1 {} {} {} {} {} {} {}",
+            self.facility as u16 * 8 + record.level.syslog_severity() as u16,
+            record
+                .timestamp
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+            self.hostname,
+            APP_NAME,
+            std::process::id(),
+            record.event,
+            structured_data,
+            record.text()
+        );
+        if message.len() <= MAX_SYSLOG_MESSAGE {
+            return message;
+        }
+        let mut end = MAX_SYSLOG_MESSAGE;
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message[..end].to_string()
+    }
+}
+
+impl LogSink for SyslogSink {
+    fn write(&self, record: &LogRecord) {
+        let message = self.format(record);
+        //a missing syslog daemon must not stop the backup, send errors are dropped
+        match &*self.transport.lock().unwrap() {
+            #[cfg(unix)]
+            Transport::Unix(socket, path) => {
+                let _ = socket.send_to(message.as_bytes(), path);
+            }
+            Transport::Udp(socket) => {
+                let _ = socket.send(message.as_bytes());
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unix_transport(path: &str) -> Result<Transport, BackupError> {
+    Ok(Transport::Unix(UnixDatagram::unbound()?, path.to_string()))
+}
+
+#[cfg(not(unix))]
+fn unix_transport(_path: &str) -> Result<Transport, BackupError> {
+    Err(BackupError::new(
+        "Unix syslog sockets are not supported on this platform",
+    ))
+}
+
+fn sd_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+pub fn parse_syslog_facility(facility: &str) -> Result<u8, String> {
+    const FACILITIES: [&str; 24] = [
+        "kern",
+        "user",
+        "mail",
+        "daemon",
+        "auth",
+        "syslog",
+        "lpr",
+        "news",
+        "uucp",
+        "cron",
+        "authpriv",
+        "ftp",
+        "ntp",
+        "security",
+        "console",
+        "solaris-cron",
+        "local0",
+        "local1",
+        "local2",
+        "local3",
+        "local4",
+        "local5",
+        "local6",
+        "local7",
+    ];
+    FACILITIES
+        .iter()
+        .position(|name| *name == facility.to_lowercase())
+        .map(|position| position as u8)
+        .ok_or(format!("Unknown syslog facility {}", facility))
+}
+
+/// Native systemd journal protocol with `DOCKERBACKUP_*` fields for every record.
+#[cfg(unix)]
+pub struct JournaldSink {
+    socket: UnixDatagram,
+}
+
+#[cfg(unix)]
+impl JournaldSink {
+    const SOCKET: &'static str = "/run/systemd/journal/socket";
+
+    pub fn connect() -> Result<Self, BackupError> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(Self::SOCKET).map_err(|e| {
+            BackupError::new(&format!(
+                "Failed to connect to journald at {}: {}",
+                Self::SOCKET,
+                e
+            ))
+        })?;
+        Ok(Self { socket })
+    }
+}
+
+#[cfg(unix)]
+impl LogSink for JournaldSink {
+    fn write(&self, record: &LogRecord) {
+        let mut datagram = Vec::new();
+        append_journal_field(&mut datagram, "MESSAGE", &record.text());
+        append_journal_field(
+            &mut datagram,
+            "PRIORITY",
+            &record.level.syslog_severity().to_string(),
+        );
+        append_journal_field(&mut datagram, "SYSLOG_IDENTIFIER", APP_NAME);
+        append_journal_field(&mut datagram, "DOCKERBACKUP_EVENT", record.event);
+        if let Some(destination) = record.destination {
+            append_journal_field(&mut datagram, "DOCKERBACKUP_DESTINATION", destination);
+        }
+        if let Some(fields) = record.fields.as_object() {
+            for (key, value) in fields {
+                let value = match value {
+                    serde_json::Value::String(value) => value.clone(),
+                    value => value.to_string(),
+                };
+                append_journal_field(
+                    &mut datagram,
+                    &format!("DOCKERBACKUP_{}", journal_field_name(key)),
+                    &value,
+                );
+            }
+        }
+        let _ = self.socket.send(&datagram);
+    }
+}
+
+#[cfg(unix)]
+fn journal_field_name(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Values with newlines use the binary form: name, newline, little endian length, value.
+#[cfg(unix)]
+fn append_journal_field(datagram: &mut Vec<u8>, name: &str, value: &str) {
+    datagram.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
@@ -1,17 +1,33 @@
 use std::{
-    collections::HashSet,
-    fs,
+    collections::{BTreeMap, HashSet},
+    env, fs,
+    io::{BufReader, Read},
     path::{Path, PathBuf},
-    process::Command,
-    sync::Arc,
+    process::{Command, Stdio},
+    sync::{atomic::AtomicU64, Arc, Mutex},
+    thread,
 };
 
-use crate::backup::destination::{BackupDestination, LocalDestination, SshDestination};
+use crate::backup::destination::{
+    BackupDestination, BorgDestination, LocalDestination, OutputLine, PluginDestination, Progress,
+    RcloneDestination, ResticDestination, SshDestination,
+};
 
-use super::{backup_result::BackupError, TargetOs};
+use super::{
+    backup_result::BackupError,
+    notification::{NotificationEvent, QuietHours, Severity, WebhookConfig, NOTIFIERS},
+    TargetOs,
+};
 
 pub fn check_docker() -> Result<(), BackupError> {
-    let status = Command::new("docker").arg("--version").status()?;
+    //only the exit status matters, the version string would break json output
+    let status = Command::new("docker")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .map_err(|e| {
+            BackupError::new(&format!("Can't continue without Docker installed: {}", e))
+        })?;
     if status.success() {
         return Ok(());
     }
@@ -22,14 +38,23 @@ pub fn check_running_containers() -> Result<String, BackupError> {
     let running_containers = Command::new("docker")
         .args(["ps", "--format", "{{.Names}}"])
         .output()?;
+    //an unreachable daemon must not look like a host without running containers
+    if !running_containers.status.success() {
+        return Err(BackupError::new(&format!(
+            "Failed to list running containers: {}",
+            String::from_utf8_lossy(&running_containers.stderr).trim()
+        )));
+    }
     let containers_list = String::from_utf8(running_containers.stdout)?;
     Ok(containers_list)
 }
 
 pub fn handle_containers(containers: &HashSet<&str>, command: &str) -> Result<(), BackupError> {
+    //docker prints every stopped or started container name, which would break json output
     let cmd_result = Command::new("docker")
         .arg(command)
         .args(containers)
+        .stdout(Stdio::null())
         .status()?;
     if cmd_result.success() {
         return Ok(());
@@ -38,7 +63,39 @@ pub fn handle_containers(containers: &HashSet<&str>, command: &str) -> Result<()
 }
 
 pub fn parse_destination_path(path: &str) -> Result<Arc<dyn BackupDestination>, String> {
-    if path.contains('@') {
+    if let Some(repository) = path.strip_prefix("restic:") {
+        if repository.is_empty() {
+            return Err(String::from("Restic repository must be provided"));
+        }
+        Ok(Arc::new(ResticDestination {
+            repository: repository.to_owned(),
+        }))
+    } else if let Some(repository) = path.strip_prefix("borg:") {
+        if repository.is_empty() {
+            return Err(String::from("Borg repository must be provided"));
+        }
+        Ok(Arc::new(BorgDestination {
+            repository: repository.to_owned(),
+        }))
+    } else if let Some(remote) = path.strip_prefix("rclone:") {
+        if !remote.contains(':') {
+            return Err(String::from(
+                "Rclone path must be in the format rclone:remote:path",
+            ));
+        }
+        Ok(Arc::new(RcloneDestination {
+            remote: remote.to_owned(),
+        }))
+    } else if let Some((scheme, _)) = path.split_once("://") {
+        let executable = find_plugin(scheme).ok_or(format!(
+            "No destination plugin found for scheme {}, dockerbackup-dest-{} must be on PATH",
+            scheme, scheme
+        ))?;
+        Ok(Arc::new(PluginDestination {
+            url: path.to_owned(),
+            executable,
+        }))
+    } else if path.contains('@') {
         let tuple: Vec<&str> = path.splitn(2, ',').collect();
         if tuple.len() != 2 {
             return Err(String::from(
@@ -49,6 +106,7 @@ pub fn parse_destination_path(path: &str) -> Result<Arc<dyn BackupDestination>,
         let parts: Vec<&str> = tuple[0].splitn(2, ':').collect();
         if parts.len() == 2 && parts[0].contains('@') {
             Ok(Arc::new(SshDestination {
+                transferred: Arc::new(AtomicU64::new(0)),
                 host: parts[0].to_owned(),
                 path: parts[1].to_owned(),
                 target_os: TargetOs::from_str(tuple[1])?,
@@ -68,11 +126,26 @@ pub fn parse_destination_path(path: &str) -> Result<Arc<dyn BackupDestination>,
     }
 }
 
+fn find_plugin(scheme: &str) -> Option<PathBuf> {
+    if scheme.is_empty()
+        || !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    let name = format!("dockerbackup-dest-{}", scheme);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_file())
+}
+
+/// Size of every volume that is backed up, by volume name.
 pub fn get_volumes_size(
     volume_path: &PathBuf,
     excluded_volumes: &[String],
-) -> Result<u64, BackupError> {
-    let mut total_size = 0;
+) -> Result<BTreeMap<String, u64>, BackupError> {
+    let mut sizes = BTreeMap::new();
     let entries = fs::read_dir(volume_path)
         .map_err(|e| BackupError::new(&format!("Failed to read volume directory: {}", e)))?;
 
@@ -86,11 +159,12 @@ pub fn get_volumes_size(
             continue;
         }
 
-        total_size += get_dir_size(&path).map_err(|e| {
+        let size = get_dir_size(&path).map_err(|e| {
             BackupError::new(&format!("Failed to calculate size for {}: {}", name, e))
         })?;
+        sizes.insert(name.to_string(), size);
     }
-    Ok(total_size)
+    Ok(sizes)
 }
 
 fn get_dir_size(path: &Path) -> std::io::Result<u64> {
@@ -112,12 +186,201 @@ fn get_dir_size(path: &Path) -> std::io::Result<u64> {
 }
 
 pub fn get_elapsed_time(start: std::time::Instant, description: &str) -> String {
-    let elapsed = start.elapsed();
+    format!("{}: {}", description, format_duration(start.elapsed()))
+}
+
+pub fn format_duration(duration: std::time::Duration) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        duration.as_secs() / 3600,
+        duration.as_secs() % 3600 / 60,
+        duration.as_secs() % 60
+    )
+}
+
+pub fn get_hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+pub fn parse_webhook_config(path: &str) -> Result<WebhookConfig, String> {
+    let config = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read webhook config {}: {}", path, e))?;
+    let mut config: WebhookConfig = serde_json::from_str(&config)
+        .map_err(|e| format!("Invalid webhook config {}: {}", path, e))?;
+
+    if reqwest::Method::from_bytes(config.method.to_uppercase().as_bytes()).is_err() {
+        return Err(format!("Invalid webhook method {}", config.method));
+    }
+
+    if let Some(template_file) = &config.template_file {
+        let template_path = Path::new(path)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(template_file);
+        config.template = Some(fs::read_to_string(&template_path).map_err(|e| {
+            format!(
+                "Failed to read webhook template {}: {}",
+                template_path.display(),
+                e
+            )
+        })?);
+    }
+
+    //a broken template would otherwise only fail after the whole backup has run
+    if let Some(template) = &config.template {
+        minijinja::Environment::new()
+            .add_template("webhook", template)
+            .map_err(|e| format!("Invalid webhook template in {}: {}", path, e))?;
+    }
+    Ok(config)
+}
+
+pub fn parse_secret_file(path: &str) -> Result<String, String> {
+    let secret = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read secret file {}: {}", path, e))?;
+    let secret = secret.trim();
+    if secret.is_empty() {
+        return Err(format!("Secret file {} is empty", path));
+    }
+    Ok(secret.to_string())
+}
+
+pub fn parse_gotify_priority(setting: &str) -> Result<(NotificationEvent, u8), String> {
+    let (event, priority) = setting.split_once('=').ok_or(String::from(
+        "Priority must be in the format event=priority",
+    ))?;
+    let priority = priority
+        .trim()
+        .parse::<u8>()
+        .ok()
+        .filter(|priority| *priority <= 10)
+        .ok_or(format!("Invalid priority {}, expected 0-10", priority))?;
+    Ok((NotificationEvent::from_str(event.trim())?, priority))
+}
+
+pub fn format_size(size: u64) -> String {
+    format!("{:.2} MB", size as f64 / (1024.0 * 1024.0))
+}
+
+pub fn watch_output<R: Read + Send + 'static>(
+    output: R,
+    dest: Arc<dyn BackupDestination>,
+    progress: Arc<Mutex<Progress>>,
+) -> thread::JoinHandle<Vec<String>> {
+    thread::spawn(move || {
+        let mut messages = Vec::new();
+        let mut line = Vec::new();
+        //progress meters redraw their line with \r, so treat it as a line break as well
+        for byte in BufReader::new(output).bytes() {
+            let byte = match byte {
+                Ok(byte) => byte,
+                Err(_) => break,
+            };
+            if byte != b'\n' && byte != b'\r' {
+                line.push(byte);
+                continue;
+            }
+            handle_output_line(&line, dest.as_ref(), &progress, &mut messages);
+            line.clear();
+        }
+        handle_output_line(&line, dest.as_ref(), &progress, &mut messages);
+        messages
+    })
+}
+
+fn handle_output_line(
+    line: &[u8],
+    dest: &dyn BackupDestination,
+    progress: &Mutex<Progress>,
+    messages: &mut Vec<String>,
+) {
+    let line = String::from_utf8_lossy(line);
+    if line.trim().is_empty() {
+        return;
+    }
+    match dest.parse_output_line(line.trim_end()) {
+        OutputLine::Progress(msg) => progress.lock().unwrap().message = Some(msg),
+        OutputLine::Transferred(bytes) => progress.lock().unwrap().transferred = Some(bytes),
+        OutputLine::Message(msg) => messages.push(msg),
+        OutputLine::Ignore => {}
+    }
+}
+
+/// Renders a progress bar with percentage, throughput and estimated time left.
+pub fn format_progress(transferred: u64, total: u64, elapsed: std::time::Duration) -> String {
+    const WIDTH: usize = 20;
+    let ratio = if total > 0 {
+        (transferred as f64 / total as f64).min(1.0)
+    } else {
+        0.0
+    };
+    let filled = (ratio * WIDTH as f64).round() as usize;
+    let speed = transferred as f64 / elapsed.as_secs_f64().max(1.0);
+    let eta = if speed > 0.0 {
+        format_duration(std::time::Duration::from_secs_f64(
+            total.saturating_sub(transferred) as f64 / speed,
+        ))
+    } else {
+        String::from("--:--:--")
+    };
     format!(
-        "{}: {:02}:{:02}:{:02}",
-        description,
-        elapsed.as_secs() / 3600,
-        elapsed.as_secs() % 3600 / 60,
-        elapsed.as_secs() % 60
+        "[{}{}] {:5.1}% {}/s ETA {}",
+        "#".repeat(filled),
+        "-".repeat(WIDTH - filled),
+        ratio * 100.0,
+        format_size(speed as u64),
+        eta
     )
 }
+
+fn split_notifier_setting(setting: &str) -> Result<(String, &str), String> {
+    let (notifier, value) = setting
+        .split_once('=')
+        .ok_or(String::from("Setting must be in the format notifier=value"))?;
+    let notifier = notifier.trim().to_lowercase();
+    if !NOTIFIERS.contains(&notifier.as_str()) {
+        return Err(format!(
+            "Unknown notifier {}, expected one of: {}",
+            notifier,
+            NOTIFIERS.join(", ")
+        ));
+    }
+    Ok((notifier, value.trim()))
+}
+
+pub fn parse_notify_on(setting: &str) -> Result<(String, Vec<NotificationEvent>), String> {
+    let (notifier, events) = split_notifier_setting(setting)?;
+    let events = events
+        .split(',')
+        .map(|event| NotificationEvent::from_str(event.trim()))
+        .collect::<Result<Vec<NotificationEvent>, String>>()?;
+    Ok((notifier, events))
+}
+
+pub fn parse_min_severity(setting: &str) -> Result<(String, Severity), String> {
+    let (notifier, severity) = split_notifier_setting(setting)?;
+    Ok((notifier, Severity::from_str(severity)?))
+}
+
+pub fn parse_quiet_hours(setting: &str) -> Result<(String, QuietHours), String> {
+    let (notifier, window) = split_notifier_setting(setting)?;
+    let (start, end) = window.split_once('-').ok_or(String::from(
+        "Quiet hours must be in the format HH:MM-HH:MM",
+    ))?;
+    let parse_time = |time: &str| {
+        chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M")
+            .map_err(|_| format!("Invalid time {}, expected HH:MM", time))
+    };
+    Ok((
+        notifier,
+        QuietHours {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        },
+    ))
+}
@@ -1,6 +1,12 @@
-use backup::DockerBackup;
+use std::process::exit;
+
+use backup::{DockerBackup, RunStatus};
 
 mod backup;
 fn main() {
-    DockerBackup::build().backup().expect("Backup failed");
+    let status = DockerBackup::build().backup().unwrap_or_else(|err| {
+        eprintln!("Backup failed: {}", err);
+        RunStatus::PreflightFailure
+    });
+    exit(status.code());
 }
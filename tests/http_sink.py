@@ -0,0 +1,33 @@
+# Minimal HTTP server storing every POST or PUT body as a separate file, used to inspect notification payloads.
+# An optional third argument makes the first requests fail with 500 to exercise retries.
+import http.server
+import os
+import sys
+
+port, output_dir = int(sys.argv[1]), sys.argv[2]
+failures = int(sys.argv[3]) if len(sys.argv) > 3 else 0
+os.makedirs(output_dir, exist_ok=True)
+
+
+class Handler(http.server.BaseHTTPRequestHandler):
+    def do_POST(self):
+        global failures
+        body = self.rfile.read(int(self.headers.get("Content-Length", 0)))
+        if failures > 0:
+            failures -= 1
+            self.send_response(500)
+            self.end_headers()
+            return
+        name = f"{len(os.listdir(output_dir)):03}{self.path.replace('/', '_')}.json"
+        with open(os.path.join(output_dir, name), "wb") as f:
+            f.write(body)
+        self.send_response(204)
+        self.end_headers()
+
+    do_PUT = do_POST
+
+    def log_message(self, *args):
+        pass
+
+
+http.server.HTTPServer(("127.0.0.1", port), Handler).serve_forever()
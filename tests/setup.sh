@@ -36,6 +36,8 @@ docker volume create backup_test_vol_excluded
 # Populate volumes
 docker run --rm -v backup_test_vol1:/data alpine sh -c "echo 'Hello World' > /data/file1.txt"
 docker run --rm -v backup_test_vol2:/data alpine sh -c "echo 'Important Data' > /data/file2.txt"
+# A directory named like the excluded volume inside another volume must still be backed up
+docker run --rm -v backup_test_vol2:/data alpine sh -c "mkdir -p /data/backup_test_vol_excluded && echo 'Keep Me' > /data/backup_test_vol_excluded/file4.txt"
 docker run --rm -v backup_test_vol_excluded:/data alpine sh -c "echo 'Skip Me' > /data/file3.txt"
 
 # Run containers
@@ -71,6 +71,1059 @@ ls -R "$REMOTE_BACKUP_PATH"
 
 echo "Remote backup verified."
 
+rm -rf /tmp/unix_restore
+mkdir -p /tmp/unix_restore
+$BINARY restore -d testuser@ssh-target:/config/remote_backup,unix --backup "$DATE_DIR" --volumes /tmp/unix_restore --volume backup_test_vol1
+
+if [ ! -f /tmp/unix_restore/backup_test_vol1/_data/file1.txt ]; then
+    echo "Volume not restored from the unix destination!"
+    exit 1
+fi
+
+# Names are checked before they reach the remote shell
+if $BINARY restore -d testuser@ssh-target:/config/remote_backup,unix --backup "$DATE_DIR" --volumes /tmp/unix_restore --volume "x'; touch /config/injected; '"; then
+    echo "Restore accepted an invalid volume name!"
+    exit 1
+fi
+if [ -e /ssh_config/injected ]; then
+    echo "Volume name was run as a remote command!"
+    exit 1
+fi
+
+echo "Unix restore verified."
+
+echo "Running Windows Destination Test..."
+# A powershell stand-in lets the linux ssh target play a windows host
+ssh testuser@ssh-target "cat > /usr/bin/powershell && chmod +x /usr/bin/powershell" < tests/windows/powershell
+ssh testuser@ssh-target "rm -rf /config/win_backup && mkdir -p /config/win_backup"
+rm -rf /tmp/win_volumes /tmp/win_restore
+mkdir -p /tmp/win_volumes/pg_data/_data /tmp/win_restore
+touch /tmp/win_volumes/backingFsBlockDev
+echo 'Postgres Data' > /tmp/win_volumes/pg_data/_data/PG_VERSION
+chown 999:999 /tmp/win_volumes/pg_data/_data/PG_VERSION
+chmod 600 /tmp/win_volumes/pg_data/_data/PG_VERSION
+ln -s PG_VERSION /tmp/win_volumes/pg_data/_data/link
+
+$BINARY -d testuser@ssh-target:/config/win_backup,windows --volumes /tmp/win_volumes
+
+if [ ! -f "/ssh_config/win_backup/$DATE_DIR/pg_data.tar" ]; then
+    echo "Volume archive not found on the windows destination!"
+    exit 1
+fi
+
+$BINARY restore -d testuser@ssh-target:/config/win_backup,windows --backup "$DATE_DIR" --volumes /tmp/win_restore
+
+if [ "$(stat -c '%u:%g %a' /tmp/win_restore/pg_data/_data/PG_VERSION)" != "999:999 600" ] || [ ! -L /tmp/win_restore/pg_data/_data/link ]; then
+    echo "Restore from the windows destination lost ownership, permissions or symlinks!"
+    exit 1
+fi
+
+echo "Windows destination verified."
+
+echo "Running Restic Backup Test..."
+export RESTIC_PASSWORD=dockerbackup-test
+RESTIC_REPO=/tmp/restic_repo
+rm -rf "$RESTIC_REPO"
+restic -r "$RESTIC_REPO" init
+
+# Two runs with --keep-last 1 should leave a single snapshot behind
+for run in 1 2; do
+    $BINARY \
+        -d restic:$RESTIC_REPO \
+        --volumes /var/lib/docker/volumes \
+        --exclude-containers container_excluded \
+        --exclude-volumes backup_test_vol_excluded \
+        --keep-last 1
+done
+
+SNAPSHOTS=$(restic -r "$RESTIC_REPO" snapshots --tag dockerbackup --json)
+if [ "$(echo "$SNAPSHOTS" | jq length)" != "1" ]; then
+    echo "Expected exactly one restic snapshot after pruning!"
+    exit 1
+fi
+
+if ! echo "$SNAPSHOTS" | jq -e ".[0].tags | index(\"run:$DATE_DIR\") and index(\"volume:backup_test_vol1\")" > /dev/null; then
+    echo "Restic snapshot is missing run or volume tags!"
+    exit 1
+fi
+
+if ! restic -r "$RESTIC_REPO" ls latest | grep -q "backup_test_vol1/_data/file1.txt"; then
+    echo "File1 not found in restic snapshot!"
+    exit 1
+fi
+
+if restic -r "$RESTIC_REPO" ls latest | grep -q "volumes/backup_test_vol_excluded"; then
+    echo "Excluded volume found in restic snapshot!"
+    exit 1
+fi
+
+if ! restic -r "$RESTIC_REPO" ls latest | grep -q "backup_test_vol2/_data/backup_test_vol_excluded/file4.txt"; then
+    echo "Directory named like the excluded volume missing from restic snapshot!"
+    exit 1
+fi
+
+echo "Restic backup verified."
+
+echo "Running Borg Backup Test..."
+BORG_REPO_PATH=/tmp/borg_repo
+rm -rf "$BORG_REPO_PATH"
+borg init --encryption=none "$BORG_REPO_PATH"
+
+$BINARY \
+    -d borg:$BORG_REPO_PATH \
+    --volumes /var/lib/docker/volumes \
+    --exclude-containers container_excluded \
+    --exclude-volumes backup_test_vol_excluded
+
+BORG_ARCHIVE="dockerbackup-$(cat /proc/sys/kernel/hostname)-$DATE_DIR"
+if ! borg list --short "$BORG_REPO_PATH" | grep -qx "$BORG_ARCHIVE"; then
+    echo "Borg archive $BORG_ARCHIVE not found!"
+    exit 1
+fi
+
+if ! borg list --short "$BORG_REPO_PATH::$BORG_ARCHIVE" | grep -q "backup_test_vol1/_data/file1.txt"; then
+    echo "File1 not found in borg archive!"
+    exit 1
+fi
+
+if borg list --short "$BORG_REPO_PATH::$BORG_ARCHIVE" | grep -q "volumes/backup_test_vol_excluded"; then
+    echo "Excluded volume found in borg archive!"
+    exit 1
+fi
+
+if ! borg list --short "$BORG_REPO_PATH::$BORG_ARCHIVE" | grep -q "backup_test_vol2/_data/backup_test_vol_excluded/file4.txt"; then
+    echo "Directory named like the excluded volume missing from borg archive!"
+    exit 1
+fi
+
+# A second run on the same day must not overwrite the existing archive
+if ! $BINARY -d borg:$BORG_REPO_PATH --volumes /var/lib/docker/volumes 2>&1 | grep -q "Archive already exists"; then
+    echo "Borg backup did not detect the existing archive!"
+    exit 1
+fi
+
+# An older archive is removed once a new one is created with --keep-last 1,
+# archives of other hosts sharing the repository are kept
+rm -rf "$BORG_REPO_PATH"
+borg init --encryption=none "$BORG_REPO_PATH"
+borg create "$BORG_REPO_PATH::dockerbackup-$(cat /proc/sys/kernel/hostname)-2000-1-1" tests/setup.sh
+borg create "$BORG_REPO_PATH::otherhost-2000-1-1" tests/setup.sh
+
+$BINARY \
+    -d borg:$BORG_REPO_PATH \
+    --volumes /var/lib/docker/volumes \
+    --exclude-containers container_excluded \
+    --exclude-volumes backup_test_vol_excluded \
+    --keep-last 1
+
+if [ "$(borg list --short "$BORG_REPO_PATH" | sort | tr '\n' ' ')" != "$BORG_ARCHIVE otherhost-2000-1-1 " ]; then
+    echo "Borg prune did not remove only the old dockerbackup archive!"
+    exit 1
+fi
+
+# scp-style repositories are checked for free space on the ssh host
+ssh testuser@ssh-target "mkdir -p /config/borg_repo"
+if $BINARY -d borg:testuser@ssh-target:/config/borg_repo --volumes /var/lib/docker/volumes 2>&1 | grep -q "df command failed"; then
+    echo "Free space of an scp-style borg repository was not checked over ssh!"
+    exit 1
+fi
+
+echo "Borg backup verified."
+
+echo "Running Rclone Backup Test..."
+# Configure a remote backed by rclone's local backend
+export RCLONE_CONFIG_LOCALTEST_TYPE=local
+rm -rf /tmp/rclone_backup
+mkdir -p /tmp/rclone_backup
+
+$BINARY \
+    -d rclone:localtest:/tmp/rclone_backup \
+    --volumes /var/lib/docker/volumes \
+    --exclude-containers container_excluded \
+    --exclude-volumes backup_test_vol_excluded
+
+RCLONE_BACKUP_PATH="/tmp/rclone_backup/$DATE_DIR"
+
+if [ ! -f "$RCLONE_BACKUP_PATH/backup_test_vol1/_data/file1.txt" ]; then
+    echo "File1 not found in rclone backup!"
+    exit 1
+fi
+
+if [ -d "$RCLONE_BACKUP_PATH/backup_test_vol_excluded" ]; then
+    echo "Excluded volume found in rclone backup!"
+    exit 1
+fi
+
+if [ ! -f "$RCLONE_BACKUP_PATH/backup_test_vol2/_data/backup_test_vol_excluded/file4.txt" ]; then
+    echo "Directory named like the excluded volume missing from rclone backup!"
+    exit 1
+fi
+
+echo "Rclone backup verified."
+
+echo "Running Progress Output Test..."
+rm -rf /tmp/progress_volumes /tmp/progress_backup /tmp/progress_state
+mkdir -p /tmp/progress_volumes/progress_data/_data /tmp/progress_backup
+touch /tmp/progress_volumes/backingFsBlockDev
+head -c 4000000 /dev/urandom > /tmp/progress_volumes/progress_data/_data/db
+
+# Throttled so the run outlasts the 30s interval between progress updates
+for format in text json; do
+    RCLONE_BWLIMIT=100k $BINARY \
+        -d rclone:localtest:/tmp/progress_backup/$format \
+        --volumes /tmp/progress_volumes \
+        --state-dir /tmp/progress_state \
+        --log-format $format > /tmp/progress_$format.txt
+done
+
+if ! grep -qE 'rclone:localtest:/tmp/progress_backup/text running time: [0-9:]+ \([0-9.]+%, ' /tmp/progress_text.txt; then
+    echo "Plain output is missing rclone progress!"
+    exit 1
+fi
+
+if ! jq -se 'map(select(.event == "progress" and .destination == "rclone:localtest:/tmp/progress_backup/json")) | length >= 2 and any(.[]; .fields.status // "" | test("^[0-9.]+%, "))' /tmp/progress_json.txt > /dev/null; then
+    echo "JSON output is missing rclone progress events!"
+    exit 1
+fi
+
+echo "Progress output verified."
+
+echo "Running Destination Plugin Test..."
+rm -rf /tmp/plugin_backup
+mkdir -p /tmp/plugin_backup/2000-1-1
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d dir:///tmp/plugin_backup \
+    --volumes /var/lib/docker/volumes \
+    --exclude-containers container_excluded \
+    --exclude-volumes backup_test_vol_excluded \
+    --keep-last 1
+
+PLUGIN_BACKUP_PATH="/tmp/plugin_backup/$DATE_DIR"
+
+if [ ! -f "$PLUGIN_BACKUP_PATH/backup_test_vol1/_data/file1.txt" ]; then
+    echo "File1 not found in plugin backup!"
+    exit 1
+fi
+
+if [ -d "$PLUGIN_BACKUP_PATH/backup_test_vol_excluded" ]; then
+    echo "Excluded volume found in plugin backup!"
+    exit 1
+fi
+
+if [ -d /tmp/plugin_backup/2000-1-1 ]; then
+    echo "Plugin prune did not remove the old backup!"
+    exit 1
+fi
+
+echo "Destination plugin verified."
+
+echo "Running Email Notification Test..."
+rm -rf /tmp/email_backup /tmp/reports
+mkdir -p /tmp/email_backup
+curl -s -X DELETE http://mailhog:8025/api/v1/messages
+
+$BINARY \
+    -d /tmp/email_backup \
+    --volumes /var/lib/docker/volumes \
+    --smtp-host mailhog \
+    --smtp-port 1025 \
+    --smtp-security none \
+    --smtp-from backup@example.com \
+    --smtp-to admin@example.com ops@example.com \
+    --smtp-attach-log \
+    --report-dir /tmp/reports \
+    --smtp-attach-report
+
+MESSAGES=$(curl -s http://mailhog:8025/api/v2/messages)
+if [ "$(echo "$MESSAGES" | jq '.total')" != "1" ]; then
+    echo "Email notification not received!"
+    exit 1
+fi
+
+if ! echo "$MESSAGES" | jq -r '.items[0].Raw.To[]' | grep -q "ops@example.com"; then
+    echo "Email notification not sent to all recipients!"
+    exit 1
+fi
+
+if ! echo "$MESSAGES" | jq -r '.items[0].Content.Body' | grep -q "dockerbackup.log"; then
+    echo "Run log not attached to email notification!"
+    exit 1
+fi
+
+if ! echo "$MESSAGES" | jq -r '.items[0].Content.Body' | grep -q "dockerbackup-report.html"; then
+    echo "HTML report not attached to email notification!"
+    exit 1
+fi
+
+# The body and attachments are base64 encoded, so no line can exceed the SMTP line limit
+RAW_MAIL=$(echo "$MESSAGES" | jq -r '.items[0].Raw.Data')
+if echo "$RAW_MAIL" | grep -q "Content-Transfer-Encoding: 8bit" || echo "$RAW_MAIL" | awk 'length > 998 { long = 1 } END { exit !long }'; then
+    echo "Email notification contains unencoded or overlong lines!"
+    exit 1
+fi
+
+if [ "$(ls /tmp/reports/*.html | wc -l)" != "2" ] || ! grep -q "email_backup" /tmp/reports/2*.html; then
+    echo "HTML report or index page not written!"
+    exit 1
+fi
+
+# An SMTP server that never answers must not stall the run
+EXIT_CODE=0
+timeout 60 $BINARY \
+    -d /tmp/email_backup \
+    --volumes /var/lib/docker/volumes \
+    --smtp-host 10.255.255.1 \
+    --smtp-security none \
+    --smtp-from backup@example.com \
+    --smtp-to admin@example.com > /dev/null || EXIT_CODE=$?
+if [ "$EXIT_CODE" = "124" ]; then
+    echo "Unreachable SMTP server stalled the run!"
+    exit 1
+fi
+
+echo "Email notification verified."
+
+echo "Running Discord Notification Test..."
+rm -rf /tmp/discord_sink /tmp/discord_backup
+mkdir -p /tmp/discord_backup
+python3 tests/http_sink.py 8090 /tmp/discord_sink &
+SINK_PID=$!
+sleep 1
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/discord_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --discord http://127.0.0.1:8090/discord \
+    --notification-mode per-destination || true
+
+kill $SINK_PID
+
+for payload in /tmp/discord_sink/*.json; do
+    if ! jq -e '.embeds[0] | (.title | length) <= 256 and (.description // "" | length) <= 4096 and (.fields | length) <= 25 and all(.fields[]; (.name | length) <= 256 and (.value | length) <= 1024 and (.value | length) > 0)' "$payload" > /dev/null; then
+        echo "Invalid Discord payload: $payload"
+        exit 1
+    fi
+done
+
+if [ "$(ls /tmp/discord_sink | wc -l)" != "2" ]; then
+    echo "Expected two Discord notifications!"
+    exit 1
+fi
+
+if ! jq -r '.embeds[0].description' /tmp/discord_sink/*.json | grep -qF 'rsync: "quoted" path C:\Backups\new\table failed'; then
+    echo "Discord payload did not preserve the error output!"
+    exit 1
+fi
+
+if [ "$(jq -s '[.[].embeds[0].color] | index(15158332) != null' /tmp/discord_sink/*.json)" != "true" ]; then
+    echo "Failed backup not reported with the failure colour!"
+    exit 1
+fi
+
+echo "Discord notification verified."
+
+echo "Running Gotify Notification Test..."
+rm -rf /tmp/gotify_sink
+python3 tests/http_sink.py 8093 /tmp/gotify_sink &
+SINK_PID=$!
+sleep 1
+
+PATH="$(pwd)/tests/plugins:$PATH" GOTIFY_TOKEN=test-token $BINARY \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --gotify http://127.0.0.1:8093/message \
+    --gotify-priority failure=9 || true
+
+kill $SINK_PID
+
+if ! jq -e '.priority == 9 and .extras["client::display"].contentType == "text/markdown"' /tmp/gotify_sink/000_message.json > /dev/null; then
+    echo "Gotify notification has wrong priority or display extras!"
+    exit 1
+fi
+
+if ! jq -r '.message' /tmp/gotify_sink/000_message.json | grep -qF 'C:\Backups\new\table'; then
+    echo "Gotify notification does not contain the error output!"
+    exit 1
+fi
+
+echo "Gotify notification verified."
+
+echo "Running ntfy Notification Test..."
+rm -rf /tmp/ntfy_sink /tmp/ntfy_backup
+mkdir -p /tmp/ntfy_backup
+python3 tests/http_sink.py 8096 /tmp/ntfy_sink 1 &
+SINK_PID=$!
+sleep 1
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/ntfy_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --ntfy http://127.0.0.1:8096/backups > /tmp/ntfy_output.txt 2>&1 || true
+
+kill $SINK_PID
+
+if ! grep -q "Sending request to ntfy.Attempt 1" /tmp/ntfy_output.txt; then
+    echo "Failed ntfy request was not retried!"
+    exit 1
+fi
+
+if [ "$(ls /tmp/ntfy_sink | wc -l)" != "1" ]; then
+    echo "Expected a single ntfy summary notification!"
+    exit 1
+fi
+
+for line in "✅ /tmp/ntfy_backup:" "❌ fail:///nowhere: Failed" "Duration:" 'C:\Backups\new\table'; do
+    if ! grep -qF "$line" /tmp/ntfy_sink/000_backups.json; then
+        echo "ntfy notification is missing: $line"
+        exit 1
+    fi
+done
+
+echo "ntfy notification verified."
+
+echo "Running Webhook Notification Test..."
+rm -rf /tmp/webhook_sink /tmp/webhook_backup /tmp/webhook
+mkdir -p /tmp/webhook_backup /tmp/webhook
+echo '{"url": "http://127.0.0.1:8095/hook", "method": "PUT", "template_file": "body.j2"}' > /tmp/webhook/config.json
+echo '{"text": {{ (hostname ~ ": backup " ~ status) | tojson }}, "destinations": {{ destinations | length }}}' > /tmp/webhook/body.j2
+# The first request fails, so the webhook is only delivered after retrying
+python3 tests/http_sink.py 8095 /tmp/webhook_sink 1 &
+SINK_PID=$!
+sleep 1
+
+$BINARY \
+    -d /tmp/webhook_backup \
+    --volumes /var/lib/docker/volumes \
+    --webhook /tmp/webhook/config.json > /tmp/webhook/output.log
+
+kill $SINK_PID
+
+if ! grep -q "Sending request to webhook.Attempt 1" /tmp/webhook/output.log; then
+    echo "Failed webhook request was not retried!"
+    exit 1
+fi
+
+if ! jq -e '(.text | endswith(": backup success")) and .destinations == 1' /tmp/webhook_sink/000_hook.json > /dev/null; then
+    echo "Webhook body was not rendered from the template!"
+    exit 1
+fi
+
+# A broken template must be rejected before the backup starts
+echo '{"text": {{ status }' > /tmp/webhook/body.j2
+EXIT_CODE=0
+$BINARY -d /tmp/webhook_backup --volumes /var/lib/docker/volumes --webhook /tmp/webhook/config.json 2> /tmp/webhook/error.log || EXIT_CODE=$?
+
+if [ "$EXIT_CODE" != "2" ] || ! grep -q "Invalid webhook template" /tmp/webhook/error.log; then
+    echo "Invalid webhook template was not rejected at startup!"
+    exit 1
+fi
+
+echo "Webhook notification verified."
+
+echo "Running Slack and Teams Notification Test..."
+rm -rf /tmp/chat_sink /tmp/chat_backup
+mkdir -p /tmp/chat_backup
+python3 tests/http_sink.py 8097 /tmp/chat_sink &
+SINK_PID=$!
+sleep 1
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/chat_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --slack http://127.0.0.1:8097/slack \
+    --teams http://127.0.0.1:8097/teams || true
+
+kill $SINK_PID
+
+if ! jq -e '.text == "Docker backup failed" and .attachments[0].color == "#e01e5a"
+    and .attachments[0].blocks[0].type == "header"
+    and (.attachments[0].blocks[1].fields | length) <= 10
+    and any(.attachments[0].blocks[1].fields[]; .type == "mrkdwn" and (.text | startswith("*❌ fail:///nowhere*")))
+    and (.attachments[0].blocks[2].text.text | contains("C:\\Backups\\new\\table") and (length <= 3000))' \
+    /tmp/chat_sink/*_slack.json > /dev/null; then
+    echo "Invalid Slack payload!"
+    exit 1
+fi
+
+if ! jq -e '.type == "message" and .attachments[0].contentType == "application/vnd.microsoft.card.adaptive"
+    and .attachments[0].content.type == "AdaptiveCard"
+    and any(.attachments[0].content.body[]; .type == "TextBlock" and .color == "Attention")
+    and any(.attachments[0].content.body[]; .type == "FactSet" and any(.facts[]; .title == "❌ fail:///nowhere"))
+    and any(.attachments[0].content.body[]; .fontType == "Monospace" and (.text | contains("C:\\Backups\\new\\table")))' \
+    /tmp/chat_sink/*_teams.json > /dev/null; then
+    echo "Invalid Teams payload!"
+    exit 1
+fi
+
+echo "Slack and Teams notifications verified."
+
+echo "Running Telegram and Matrix Notification Test..."
+rm -rf /tmp/chat_sink /tmp/chat_backup
+mkdir -p /tmp/chat_backup
+# The first two requests fail, so Telegram is only delivered after retrying
+python3 tests/http_sink.py 8098 /tmp/chat_sink 2 &
+SINK_PID=$!
+sleep 1
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/chat_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --telegram-api http://127.0.0.1:8098 \
+    --telegram-token 123:abc \
+    --telegram-chat 42 \
+    --matrix-homeserver http://127.0.0.1:8098 \
+    --matrix-token secret \
+    --matrix-room '!room:example.org' > /tmp/chat_output.log || true
+
+kill $SINK_PID
+
+if ! grep -q "Sending request to Telegram.Attempt 2" /tmp/chat_output.log; then
+    echo "Telegram notification was not retried!"
+    exit 1
+fi
+
+if ! jq -e '.chat_id == "42" and .parse_mode == "HTML"
+    and (.text | startswith("<b>❌ Docker backup failed</b>"))
+    and (.text | contains("&quot;quoted&quot; path C:\\Backups\\new\\table"))' \
+    /tmp/chat_sink/*_bot123:abc_sendMessage.json > /dev/null; then
+    echo "Invalid Telegram payload!"
+    exit 1
+fi
+
+if ! jq -e '.msgtype == "m.text" and .format == "org.matrix.custom.html"
+    and (.body | contains("fail:///nowhere"))
+    and (.formatted_body | contains("<br>") and contains("<pre>"))' \
+    /tmp/chat_sink/*_matrix_client_v3_rooms_*.json > /dev/null; then
+    echo "Invalid Matrix payload!"
+    exit 1
+fi
+
+# Many failed destinations must still fit in a single Telegram message with balanced tags
+rm -rf /tmp/chat_sink
+python3 tests/http_sink.py 8098 /tmp/chat_sink &
+SINK_PID=$!
+sleep 1
+
+FAILING_DESTINATIONS=()
+for i in $(seq 1 40); do
+    FAILING_DESTINATIONS+=(-d "fail:///nowhere$i")
+done
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    "${FAILING_DESTINATIONS[@]}" \
+    --volumes /var/lib/docker/volumes \
+    --telegram-api http://127.0.0.1:8098 \
+    --telegram-token 123:abc \
+    --telegram-chat 42 > /dev/null || true
+
+kill $SINK_PID
+
+if ! jq -e '.text | length <= 4096
+    and ([scan("<b>")] | length) == ([scan("</b>")] | length)
+    and ([scan("<pre>")] | length) == ([scan("</pre>")] | length)' \
+    /tmp/chat_sink/*_bot123:abc_sendMessage.json > /dev/null; then
+    echo "Telegram message is too long or has unbalanced tags!"
+    exit 1
+fi
+
+echo "Telegram and Matrix notifications verified."
+
+echo "Running Notification Mode Test..."
+echo '{"url": "http://127.0.0.1:8099/hook"}' > /tmp/mode_webhook.json
+for mode in summary per-destination; do
+    rm -rf /tmp/mode_sink /tmp/mode_backup1 /tmp/mode_backup2
+    mkdir -p /tmp/mode_backup1 /tmp/mode_backup2
+    python3 tests/http_sink.py 8099 /tmp/mode_sink &
+    SINK_PID=$!
+    sleep 1
+
+    PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+        -d /tmp/mode_backup1 \
+        -d /tmp/mode_backup2 \
+        -d fail:///nowhere \
+        --volumes /var/lib/docker/volumes \
+        --webhook /tmp/mode_webhook.json \
+        --notification-mode $mode || true
+
+    kill $SINK_PID
+
+    if [ "$mode" = "summary" ]; then
+        if [ "$(ls /tmp/mode_sink | wc -l)" != "1" ] || [ "$(jq '.destinations | length' /tmp/mode_sink/000_hook.json)" != "3" ]; then
+            echo "Summary mode did not send exactly one notification covering every destination!"
+            exit 1
+        fi
+    elif [ "$(jq -rs '[.[].destination] | sort | join(" ")' /tmp/mode_sink/*.json)" != "/tmp/mode_backup1 /tmp/mode_backup2 fail:///nowhere" ]; then
+        echo "Per-destination mode did not send one notification per destination!"
+        exit 1
+    fi
+done
+
+echo "Notification modes verified."
+
+echo "Running Quiet Hours Test..."
+#windows are built around the current time, INSIDE wraps past midnight unless it's shortly after 01:00
+INSIDE="$(date -d '-1 hour' +%H:%M)-$(date -d '-2 hours' +%H:%M)"
+OUTSIDE="$(date -d '+1 hour' +%H:%M)-$(date -d '-1 hour' +%H:%M)"
+echo '{"url": "http://127.0.0.1:8092/hook"}' > /tmp/quiet_webhook.json
+rm -rf /tmp/quiet_sink /tmp/quiet_state
+python3 tests/http_sink.py 8092 /tmp/quiet_sink &
+SINK_PID=$!
+sleep 1
+
+quiet_run() {
+    PATH="$(pwd)/tests/plugins:$PATH" timeout 60 $BINARY \
+        -d "$1" \
+        --volumes /var/lib/docker/volumes \
+        --webhook /tmp/quiet_webhook.json \
+        --state-dir /tmp/quiet_state \
+        "${@:2}" || true
+}
+sent() {
+    ls /tmp/quiet_sink 2>/dev/null | wc -l
+}
+delayed() {
+    jq length /tmp/quiet_state/delayed_notifications.json
+}
+
+#a success inside the window is saved instead of sent, and the run doesn't wait for the window to end
+quiet_run "$(mktemp -d)" --quiet-hours "all=$INSIDE"
+if [ "$(sent)" != "0" ] || [ "$(delayed)" != "1" ]; then
+    echo "Success inside quiet hours was not delayed!"
+    exit 1
+fi
+
+#failures are critical and bypass quiet hours
+quiet_run fail:///nowhere --quiet-hours "all=$INSIDE"
+if [ "$(sent)" != "1" ] || [ "$(jq -r .status /tmp/quiet_sink/000_hook.json)" != "failure" ] || [ "$(delayed)" != "1" ]; then
+    echo "Failure was held back by quiet hours!"
+    exit 1
+fi
+
+#a window that doesn't contain the current time sends right away
+quiet_run "$(mktemp -d)" --quiet-hours "all=$OUTSIDE"
+if [ "$(sent)" != "2" ] || [ "$(delayed)" != "1" ]; then
+    echo "Notification outside quiet hours was not sent!"
+    exit 1
+fi
+
+#per-service rules start from the "all" rule and override only what they set
+quiet_run "$(mktemp -d)" --quiet-hours "all=$INSIDE" --notify-on webhook=success,failure
+if [ "$(sent)" != "2" ] || [ "$(delayed)" != "2" ]; then
+    echo "Webhook rule did not inherit quiet hours from all!"
+    exit 1
+fi
+quiet_run "$(mktemp -d)" --quiet-hours "all=$INSIDE" --quiet-hours "webhook=$OUTSIDE"
+if [ "$(sent)" != "3" ] || [ "$(delayed)" != "2" ]; then
+    echo "Webhook quiet hours did not override all!"
+    exit 1
+fi
+quiet_run "$(mktemp -d)" --notify-on all=failure --quiet-hours "webhook=$OUTSIDE"
+if [ "$(sent)" != "3" ] || [ "$(delayed)" != "2" ]; then
+    echo "Webhook rule did not inherit notify-on from all!"
+    exit 1
+fi
+quiet_run "$(mktemp -d)" --notify-min-severity webhook=warning
+if [ "$(sent)" != "3" ]; then
+    echo "Success below the minimum severity was sent!"
+    exit 1
+fi
+
+#once the window has ended the next run sends the saved notifications first
+jq '.[0].until = "2000-01-01T07:00:00+00:00"' /tmp/quiet_state/delayed_notifications.json > /tmp/quiet_delayed.json
+mv /tmp/quiet_delayed.json /tmp/quiet_state/delayed_notifications.json
+quiet_run "$(mktemp -d)" --notify-on all=failure
+if [ "$(sent)" != "4" ] || [ "$(jq -r .status /tmp/quiet_sink/003_hook.json)" != "success" ] || [ "$(delayed)" != "1" ]; then
+    echo "Delayed notification was not sent after quiet hours!"
+    exit 1
+fi
+
+#a run that starts inside the next window still sends what is due, e.g. a nightly cron inside quiet hours
+jq '.[0].until = "2000-01-01T07:00:00+00:00"' /tmp/quiet_state/delayed_notifications.json > /tmp/quiet_delayed.json
+mv /tmp/quiet_delayed.json /tmp/quiet_state/delayed_notifications.json
+quiet_run "$(mktemp -d)" --notify-on all=failure --quiet-hours "all=$INSIDE"
+if [ "$(sent)" != "5" ] || [ "$(jq -r .status /tmp/quiet_sink/004_hook.json)" != "success" ] || [ "$(delayed)" != "0" ]; then
+    echo "Delayed notification was held back again by the next quiet hours!"
+    exit 1
+fi
+
+#a delayed email is sent with the log of the run it describes, not of the run that sends it
+curl -s -X DELETE http://mailhog:8025/api/v1/messages
+EMAIL_ARGS=(--smtp-host mailhog --smtp-port 1025 --smtp-security none --smtp-from backup@example.com --smtp-to admin@example.com --smtp-attach-log)
+QUIET_DEST=$(mktemp -d)
+quiet_run "$QUIET_DEST" "${EMAIL_ARGS[@]}" --notify-on webhook=failure --quiet-hours "email=$INSIDE"
+jq '.[0].until = "2000-01-01T07:00:00+00:00"' /tmp/quiet_state/delayed_notifications.json > /tmp/quiet_delayed.json
+mv /tmp/quiet_delayed.json /tmp/quiet_state/delayed_notifications.json
+quiet_run "$(mktemp -d)" "${EMAIL_ARGS[@]}" --notify-on all=failure
+MESSAGES=$(curl -s http://mailhog:8025/api/v2/messages)
+if [ "$(echo "$MESSAGES" | jq '.total')" != "1" ] || ! echo "$MESSAGES" | jq -r '.items[0].MIME.Parts[] | select(.Headers["Content-Disposition"][0] // "" | contains("dockerbackup.log")) | .Body' | base64 -d | grep -q "$QUIET_DEST"; then
+    echo "Delayed email did not keep the log of its run!"
+    exit 1
+fi
+
+kill $SINK_PID
+echo "Quiet hours verified."
+
+echo "Running Heartbeat Test..."
+rm -rf /tmp/heartbeat_sink /tmp/heartbeat_backup
+mkdir -p /tmp/heartbeat_backup
+python3 tests/http_sink.py 8091 /tmp/heartbeat_sink &
+SINK_PID=$!
+sleep 1
+
+$BINARY \
+    -d /tmp/heartbeat_backup \
+    --volumes /var/lib/docker/volumes \
+    --heartbeat http://127.0.0.1:8091/ping/test
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --heartbeat http://127.0.0.1:8091/ping/test || true
+
+# Without Docker the run stops before any destination but still reports the failure
+set +e
+PATH=/nonexistent $BINARY \
+    -d /tmp/heartbeat_backup \
+    --volumes /var/lib/docker/volumes \
+    --summary-json /tmp/nodocker_summary.json \
+    --heartbeat http://127.0.0.1:8091/ping/test 2> /tmp/nodocker_error.txt
+EXIT_CODE=$?
+set -e
+
+kill $SINK_PID
+
+if [ "$EXIT_CODE" != "5" ] || [ "$(grep -c "Can't continue without Docker installed" /tmp/nodocker_error.txt)" != "1" ]; then
+    echo "Missing Docker was not reported once with exit code 5, got $EXIT_CODE: $(cat /tmp/nodocker_error.txt)"
+    exit 1
+fi
+
+if [ "$(jq -r .status /tmp/nodocker_summary.json)" != "preflight_failure" ]; then
+    echo "Missing Docker was not written to the summary file!"
+    exit 1
+fi
+
+if [ "$(ls /tmp/heartbeat_sink | tr '\n' ' ')" != "000_ping_test_start.json 001_ping_test.json 002_ping_test_start.json 003_ping_test_fail.json 004_ping_test_start.json 005_ping_test_fail.json " ]; then
+    echo "Unexpected heartbeat pings: $(ls /tmp/heartbeat_sink)"
+    exit 1
+fi
+
+if ! grep -qF 'C:\Backups\new\table' /tmp/heartbeat_sink/003_ping_test_fail.json; then
+    echo "Failure ping does not contain the log excerpt!"
+    exit 1
+fi
+
+echo "Heartbeat pings verified."
+
+echo "Running Plain Output Test..."
+rm -rf /tmp/plain_backup
+mkdir -p /tmp/plain_backup
+
+$BINARY -d /tmp/plain_backup --volumes /var/lib/docker/volumes > /tmp/plain_output.log
+
+if grep -q $'\e' /tmp/plain_output.log; then
+    echo "Escape codes found in non-interactive output!"
+    exit 1
+fi
+
+if ! grep -qE '^\[[0-9-]+ [0-9:]+\] OK +All backups finished' /tmp/plain_output.log; then
+    echo "Plain output lines are missing timestamps or levels!"
+    exit 1
+fi
+
+echo "Plain output verified."
+
+echo "Running JSON Log Test..."
+rm -rf /tmp/json_backup
+mkdir -p /tmp/json_backup
+
+$BINARY -d /tmp/json_backup --volumes /var/lib/docker/volumes --log-format json > /tmp/json_output.log
+
+if ! jq -e . /tmp/json_output.log > /dev/null; then
+    echo "JSON log contains lines that are not valid JSON!"
+    exit 1
+fi
+
+for event in run_started containers_stopped destination_started destination_finished containers_started run_finished; do
+    if ! jq -e --arg event "$event" 'select(.event == $event)' /tmp/json_output.log > /dev/null; then
+        echo "JSON log is missing the $event event!"
+        exit 1
+    fi
+done
+
+echo "JSON log verified."
+
+echo "Running Log File Test..."
+rm -rf /tmp/log_backup /tmp/logs
+mkdir -p /tmp/log_backup /tmp/logs
+head -c 2000000 /dev/zero > /tmp/logs/dockerbackup.log
+
+$BINARY \
+    -d /tmp/log_backup \
+    --volumes /var/lib/docker/volumes \
+    --log-file /tmp/logs/dockerbackup.log \
+    --log-max-size 1 \
+    --store-run-log
+
+if [ ! -f /tmp/logs/dockerbackup.log.1 ]; then
+    echo "Oversized log file was not rotated!"
+    exit 1
+fi
+
+if ! grep -q "All backups finished" /tmp/logs/dockerbackup.log; then
+    echo "Log file does not contain the run log!"
+    exit 1
+fi
+
+if ! grep -q "All backups finished" "/tmp/log_backup/$DATE_DIR.log"; then
+    echo "Run log not stored next to the backup!"
+    exit 1
+fi
+
+echo "Log file verified."
+
+echo "Running Syslog Test..."
+rm -rf /tmp/syslog_backup /tmp/syslog.log
+mkdir -p /tmp/syslog_backup
+python3 -c '
+import socket
+sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+sock.bind(("127.0.0.1", 5514))
+with open("/tmp/syslog.log", "ab", buffering=0) as log:
+    while True:
+        log.write(sock.recv(65535) + b"\n")
+' &
+SYSLOG_PID=$!
+sleep 1
+
+$BINARY \
+    -d /tmp/syslog_backup \
+    --volumes /var/lib/docker/volumes \
+    --syslog udp://127.0.0.1:5514 \
+    --syslog-facility local0
+sleep 1
+kill $SYSLOG_PID
+
+if ! grep -qE '^<133>1 [0-9]{4}-[0-9]{2}-[0-9]{2}T[0-9]{2}:[0-9]{2}:[0-9]{2}\.[0-9]{6}(Z|[+-][0-9]{2}:[0-9]{2}) [^ ]+ dockerbackup [0-9]+ run_finished \[dockerbackup@32473 event="run_finished"\]' /tmp/syslog.log; then
+    echo "Syslog did not receive RFC 5424 run_finished message!"
+    exit 1
+fi
+
+if ! grep -q 'destination_finished \[dockerbackup@32473 event="destination_finished" destination="' /tmp/syslog.log; then
+    echo "Syslog messages are missing the destination!"
+    exit 1
+fi
+
+echo "Syslog verified."
+
+echo "Running Metrics Test..."
+rm -rf /tmp/metrics_backup /tmp/metrics /tmp/pushgateway_sink
+mkdir -p /tmp/metrics_backup /tmp/metrics
+python3 tests/http_sink.py 8094 /tmp/pushgateway_sink &
+SINK_PID=$!
+sleep 1
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/metrics_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --metrics-file /tmp/metrics/dockerbackup.prom \
+    --pushgateway http://127.0.0.1:8094 || true
+
+kill $SINK_PID
+
+if ! grep -q '^dockerbackup_destination_success{destination="/tmp/metrics_backup"} 1$' /tmp/metrics/dockerbackup.prom; then
+    echo "Metrics file does not report the successful destination!"
+    exit 1
+fi
+
+if ! grep -q '^dockerbackup_destination_consecutive_failures{destination="fail:///nowhere"} 1$' /tmp/metrics/dockerbackup.prom; then
+    echo "Metrics file does not report the failed destination!"
+    exit 1
+fi
+
+if ! grep -q '^dockerbackup_volume_size_bytes{volume=' /tmp/metrics/dockerbackup.prom; then
+    echo "Metrics file is missing volume sizes!"
+    exit 1
+fi
+
+if ! grep -qE '^dockerbackup_destination_transferred_bytes\{destination="/tmp/metrics_backup"\} [1-9][0-9]*$' /tmp/metrics/dockerbackup.prom; then
+    echo "Metrics file does not report the bytes transferred to the destination!"
+    exit 1
+fi
+
+if grep -q '^dockerbackup_destination_transferred_bytes{destination="fail:///nowhere"}' /tmp/metrics/dockerbackup.prom; then
+    echo "Metrics file reports transferred bytes for a destination that doesn't count them!"
+    exit 1
+fi
+
+if ! diff -q /tmp/metrics/dockerbackup.prom /tmp/pushgateway_sink/000_metrics_job_dockerbackup_instance_*.json; then
+    echo "Metrics were not pushed to the Pushgateway!"
+    exit 1
+fi
+
+echo "Metrics verified."
+
+echo "Running Run History Test..."
+rm -rf /tmp/history_backup /tmp/history_state
+mkdir -p /tmp/history_backup
+
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/history_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --state-dir /tmp/history_state || true
+
+$BINARY history --state-dir /tmp/history_state
+$BINARY status --state-dir /tmp/history_state
+
+if ! $BINARY status --state-dir /tmp/history_state | grep -qE '^/tmp/history_backup +[0-9-]+ [0-9:]+ +ok '; then
+    echo "Status does not show the successful destination!"
+    exit 1
+fi
+
+if [ "$($BINARY history --destination fail:///nowhere --format json --state-dir /tmp/history_state | jq -r '.[0].destinations[] | select(.destination == "fail:///nowhere") | .success')" != "false" ]; then
+    echo "History does not record the failed destination!"
+    exit 1
+fi
+
+# A run that can't reach the Docker daemon is recorded without destinations
+DOCKER_HOST=unix:///nonexistent.sock $BINARY \
+    -d /tmp/history_backup \
+    --volumes /var/lib/docker/volumes \
+    --state-dir /tmp/history_state || true
+
+if ! $BINARY history --format json --state-dir /tmp/history_state | jq -e '.[0] | .success == false and .destinations == [] and (.warnings[0] | startswith("Failed to list running containers"))' > /dev/null; then
+    echo "History does not record the preflight failure!"
+    exit 1
+fi
+
+if ! $BINARY history --state-dir /tmp/history_state | grep -qE '^[0-9]+ +[0-9-]+ [0-9:]+ +- +failed .*Failed to list running containers'; then
+    echo "History table does not show the preflight failure!"
+    exit 1
+fi
+
+echo "Run history verified."
+
+echo "Running Exit Code Test..."
+rm -rf /tmp/exit_backup /tmp/summary.json
+mkdir -p /tmp/exit_backup
+
+EXIT_CODE=0
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY \
+    -d /tmp/exit_backup \
+    -d fail:///nowhere \
+    --volumes /var/lib/docker/volumes \
+    --summary-json /tmp/summary.json || EXIT_CODE=$?
+
+if [ "$EXIT_CODE" != "3" ]; then
+    echo "Partial failure exited with $EXIT_CODE instead of 3!"
+    exit 1
+fi
+
+if [ "$(jq -r '.status' /tmp/summary.json)" != "partial_failure" ] || \
+    [ "$(jq -r '.destinations[] | select(.destination == "/tmp/exit_backup") | .success' /tmp/summary.json)" != "true" ]; then
+    echo "Summary file does not describe the partial failure!"
+    exit 1
+fi
+
+EXIT_CODE=0
+PATH="$(pwd)/tests/plugins:$PATH" $BINARY -d fail:///nowhere --volumes /var/lib/docker/volumes || EXIT_CODE=$?
+
+if [ "$EXIT_CODE" != "4" ]; then
+    echo "Total failure exited with $EXIT_CODE instead of 4!"
+    exit 1
+fi
+
+echo "Exit codes verified."
+
+echo "Running Volume Size Anomaly Test..."
+rm -rf /tmp/size_volumes /tmp/size_backup /tmp/size_state /tmp/size_summary.json
+mkdir -p /tmp/size_volumes/app_data/_data /tmp/size_volumes/old_data/_data /tmp/size_backup
+touch /tmp/size_volumes/backingFsBlockDev
+head -c 5000000 /dev/urandom > /tmp/size_volumes/app_data/_data/db
+head -c 5000000 /dev/urandom > /tmp/size_volumes/old_data/_data/db
+
+rm -rf /tmp/size_restic
+restic init --repo /tmp/size_restic
+
+$BINARY -d /tmp/size_backup -d restic:/tmp/size_restic --volumes /tmp/size_volumes --state-dir /tmp/size_state --keep-last 1
+
+# Simulate an app wiping its data and a removed volume
+head -c 100000 /dev/urandom > /tmp/size_volumes/app_data/_data/db
+rm -rf /tmp/size_volumes/old_data /tmp/size_backup/*
+
+echo '{"url": "http://127.0.0.1:8100/hook"}' > /tmp/size_webhook.json
+rm -rf /tmp/size_sink
+python3 tests/http_sink.py 8100 /tmp/size_sink &
+SINK_PID=$!
+sleep 1
+
+# Anomalies are reported, but the backup itself succeeded
+$BINARY \
+    -d /tmp/size_backup \
+    -d restic:/tmp/size_restic \
+    --keep-last 1 \
+    --volumes /tmp/size_volumes \
+    --state-dir /tmp/size_state \
+    --summary-json /tmp/size_summary.json \
+    --webhook /tmp/size_webhook.json \
+    --notify-min-severity webhook=warning \
+    --log-format json > /tmp/size_output.log
+
+if [ "$(jq -r '.status + " " + (.warnings | length | tostring)' /tmp/size_summary.json)" != "success 0" ]; then
+    echo "Size anomalies changed the run status!"
+    exit 1
+fi
+
+# The sizes are checked before the destinations start, so the good snapshot isn't pruned
+if [ "$(restic -r /tmp/size_restic snapshots --tag dockerbackup --json | jq length)" != "2" ] || ! restic -r /tmp/size_restic ls "$(restic -r /tmp/size_restic snapshots --tag dockerbackup --json | jq -r 'sort_by(.time)[0].id')" | grep -q "old_data/_data/db"; then
+    echo "Snapshot from before the size anomaly was pruned!"
+    exit 1
+fi
+
+if ! jq -r '.anomalies[]' /tmp/size_summary.json | grep -q "Volume app_data shrank by 98%"; then
+    echo "Shrunk volume not reported!"
+    exit 1
+fi
+
+if ! jq -r '.anomalies[]' /tmp/size_summary.json | grep -q "Volume old_data disappeared"; then
+    echo "Disappeared volume not reported!"
+    exit 1
+fi
+
+if [ "$(jq -s 'map(select(.event == "volume_size_anomaly")) | length' /tmp/size_output.log)" != "2" ] || ! jq -se 'map(select(.event == "run_finished"))[0].fields | .success and (.anomalies | length) == 2' /tmp/size_output.log > /dev/null; then
+    echo "Size anomalies missing from the JSON log!"
+    exit 1
+fi
+
+# A notifier that only wants warnings receives the anomalies, in both notification modes
+if [ "$(ls /tmp/size_sink | wc -l)" != "1" ] || [ "$(jq -r '.status + " " + (.anomalies | length | tostring)' /tmp/size_sink/000_hook.json)" != "warning 2" ]; then
+    echo "Size anomalies were not sent as a warning!"
+    exit 1
+fi
+
+head -c 1000 /dev/urandom > /tmp/size_volumes/app_data/_data/db
+rm -rf /tmp/size_backup/*
+$BINARY \
+    -d /tmp/size_backup \
+    --volumes /tmp/size_volumes \
+    --state-dir /tmp/size_state \
+    --webhook /tmp/size_webhook.json \
+    --notify-min-severity webhook=warning \
+    --notification-mode per-destination
+kill $SINK_PID
+
+if [ "$(ls /tmp/size_sink | wc -l)" != "2" ] || [ "$(jq -r '.status + " " + (.anomalies[0] | startswith("Volume app_data shrank") | tostring)' /tmp/size_sink/001_hook.json)" != "warning true" ]; then
+    echo "Size anomalies were not sent in per-destination mode!"
+    exit 1
+fi
+
+echo "Volume size anomalies verified."
+
 echo "Running Space Check Test..."
 
 # 1. Local Space Check